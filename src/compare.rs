@@ -0,0 +1,1723 @@
+//! Pairing up the entries and groups of two databases and comparing them field by field, along
+//! with merging one into the other by the same pairing.
+
+use chrono::{NaiveDateTime, Utc};
+use keepass::config::{DatabaseConfig, DatabaseVersion};
+use keepass::db::{Entry, Group, Node, Value};
+use keepass::Database;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::difference::{Difference, DifferenceType, EntryId, EntrySnapshot};
+use crate::fields::{
+    attachment_hashes, custom_icon_hashes, describe_expiry, expiry_time, history_summary, is_expired, password_strength,
+    tag_set, AutoTypeSettings, EntryIcon, OtpConfig, OTP_FIELDS, STANDARD_FIELDS,
+};
+
+/// How entries of the two databases are paired up before their fields are compared.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)]
+pub enum MatchStrategy {
+    ByUuid,
+    ByTitle,
+    ByGroupPathAndTitle,
+    /// The value of a chosen field, such as a custom "ID"
+    ByField,
+}
+
+impl MatchStrategy {
+    pub const ALL: [MatchStrategy; 4] = [
+        MatchStrategy::ByUuid,
+        MatchStrategy::ByTitle,
+        MatchStrategy::ByGroupPathAndTitle,
+        MatchStrategy::ByField,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchStrategy::ByUuid => "UUID",
+            MatchStrategy::ByTitle => "Title",
+            MatchStrategy::ByGroupPathAndTitle => "Group path and title",
+            MatchStrategy::ByField => "Field",
+        }
+    }
+}
+
+/// Which side of a differing entry merging keeps.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Resolution {
+    #[default]
+    KeepNewer,
+    KeepFirst,
+    KeepSecond,
+    Skip,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 4] = [
+        Resolution::KeepNewer,
+        Resolution::KeepFirst,
+        Resolution::KeepSecond,
+        Resolution::Skip,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Resolution::KeepNewer => "Keep newer",
+            Resolution::KeepFirst => "Keep DB1",
+            Resolution::KeepSecond => "Keep DB2",
+            Resolution::Skip => "Skip",
+        }
+    }
+}
+
+/// An entry's state in one database of a drift matrix.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DriftState {
+    Missing,
+    Matches,
+    Differs,
+}
+
+/// One entry's state in every database, measured against the first database.
+pub struct DriftRow {
+    pub title: String,
+    pub group_path: String,
+    pub states: Vec<DriftState>,
+}
+
+/// Entries that are missing or differ somewhere across all compared databases.
+pub struct DriftMatrix {
+    pub sources: Vec<String>,
+    pub rows: Vec<DriftRow>,
+}
+
+/// A group as shown in the tree explorer, holding just enough to browse it and find its differences.
+pub struct ExplorerGroup {
+    pub uuid: Uuid,
+    pub name: String,
+    pub groups: Vec<ExplorerGroup>,
+    pub entries: Vec<ExplorerEntry>,
+    /// Whether any entry in this group or below it has differences
+    pub has_differences: bool,
+}
+
+impl ExplorerGroup {
+    /// Paths of every group below this one, parents before their children.
+    pub fn group_paths(&self) -> Vec<Vec<String>> {
+        let mut paths = Vec::new();
+        for child in &self.groups {
+            paths.push(vec![child.name.clone()]);
+            for mut path in child.group_paths() {
+                path.insert(0, child.name.clone());
+                paths.push(path);
+            }
+        }
+        paths
+    }
+}
+
+/// An entry as shown in the tree explorer.
+pub struct ExplorerEntry {
+    pub title: String,
+    /// Key of the entry's differences; absent for entries left out of the comparison
+    pub key: Option<String>,
+    pub has_differences: bool,
+}
+
+/// Builds the explorer tree below `group`, looking entries' difference keys up by UUID in `keys`.
+pub fn explorer_group(group: &Group, keys: &HashMap<Uuid, String>, differing: &HashSet<&str>) -> ExplorerGroup {
+    let groups: Vec<ExplorerGroup> = group.groups().into_iter().map(|g| explorer_group(g, keys, differing)).collect();
+    let entries: Vec<ExplorerEntry> = group
+        .entries()
+        .into_iter()
+        .map(|entry| {
+            let key = keys.get(&entry.uuid);
+            ExplorerEntry {
+                title: display_title(entry.get_title()),
+                has_differences: key.is_some_and(|key| differing.contains(key.as_str())),
+                key: key.cloned(),
+            }
+        })
+        .collect();
+    ExplorerGroup {
+        uuid: group.uuid,
+        name: group.name.clone(),
+        has_differences: groups.iter().any(|g| g.has_differences) || entries.iter().any(|e| e.has_differences),
+        groups,
+        entries,
+    }
+}
+
+/// What merging does with one entry.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MergeAction {
+    /// Replace the first database's version with the second's
+    Overwrite,
+    /// Add an entry found only in the second database
+    Add,
+    /// Drop an entry found only in the first database
+    Remove,
+    /// Keep the first database's version of an entry that differs
+    KeepFirst,
+    /// Leave out an entry found only in the second database
+    LeaveOut,
+    /// Keep an entry that is the same on both sides
+    Identical,
+}
+
+impl MergeAction {
+    /// Whether the merged database ends up different from the first database for this entry.
+    pub fn changes_entry(&self) -> bool {
+        matches!(self, MergeAction::Overwrite | MergeAction::Add | MergeAction::Remove)
+    }
+}
+
+/// One step of a merge preview.
+pub struct PlannedAction {
+    pub title: String,
+    pub group_path: String,
+    pub action: MergeAction,
+}
+
+/// An entry found in both databases without any difference between them.
+pub struct IdenticalEntry {
+    pub title: String,
+    pub username: String,
+    pub group_path: String,
+}
+
+/// An entry together with the names of the groups leading to it, excluding the root group.
+struct EntryRef<'a> {
+    entry: &'a Entry,
+    group_path: Vec<&'a str>,
+    in_recycle_bin: bool,
+}
+
+/// A group below the root together with the names of its ancestors, excluding the root group.
+struct GroupRef<'a> {
+    group: &'a Group,
+    parent_path: Vec<&'a str>,
+    in_recycle_bin: bool,
+}
+
+/// Per-database tables built once per comparison and shared by every entry compared.
+struct DatabaseLookups<'a> {
+    custom_icons: HashMap<Uuid, String>,
+    references: FieldReferences<'a>,
+}
+
+impl<'a> DatabaseLookups<'a> {
+    fn of(db: &'a Database) -> Self {
+        DatabaseLookups {
+            custom_icons: custom_icon_hashes(db),
+            references: FieldReferences::of(db),
+        }
+    }
+}
+
+/// Resolves KeePass field references (`{REF:P@I:<uuid>}` and friends) against the entries of one database.
+struct FieldReferences<'a> {
+    entries: Vec<&'a Entry>,
+}
+
+/// A field value with its references substituted, and the references that matched no entry.
+struct ResolvedValue {
+    value: Zeroizing<String>,
+    dangling: Vec<String>,
+}
+
+impl<'a> FieldReferences<'a> {
+    /// References may point at fields that are references themselves; chains longer than this are left as-is.
+    const MAX_DEPTH: usize = 12;
+
+    fn of(db: &'a Database) -> Self {
+        fn collect<'a>(group: &'a Group, entries: &mut Vec<&'a Entry>) {
+            for node in &group.children {
+                match node {
+                    Node::Entry(entry) => entries.push(entry),
+                    Node::Group(child) => collect(child, entries),
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        collect(&db.root, &mut entries);
+        FieldReferences { entries }
+    }
+
+    fn resolve(&self, value: &str) -> ResolvedValue {
+        let mut dangling = Vec::new();
+        let value = Zeroizing::new(self.substitute(value, 0, &mut dangling));
+        ResolvedValue { value, dangling }
+    }
+
+    fn substitute(&self, value: &str, depth: usize, dangling: &mut Vec<String>) -> String {
+        // Placeholders are ASCII, so byte offsets found in the upper-cased copy line up with the original
+        let upper = value.to_ascii_uppercase();
+        let mut result = String::new();
+        let mut rest = 0;
+        while let Some(start) = upper[rest..].find("{REF:").map(|offset| rest + offset) {
+            let Some(end) = upper[start..].find('}').map(|offset| start + offset) else {
+                break;
+            };
+            let reference = &value[start..=end];
+            result.push_str(&value[rest..start]);
+            match self.lookup(&value[start + 5..end]) {
+                Some(target) if depth < Self::MAX_DEPTH => {
+                    result.push_str(&self.substitute(&target, depth + 1, dangling));
+                }
+                Some(target) => result.push_str(&target),
+                None => {
+                    dangling.push(reference.to_string());
+                    result.push_str(reference);
+                }
+            }
+            rest = end + 1;
+        }
+        result.push_str(&value[rest..]);
+        result
+    }
+
+    /// Looks up the body of a reference, `<wanted>@<search in>:<text>`, returning the wanted field if an entry
+    /// matches. Malformed references count as dangling, as KeePass leaves them unresolved too.
+    fn lookup(&self, body: &str) -> Option<Zeroizing<String>> {
+        let (wanted, search) = body.split_once('@')?;
+        let (search_in, text) = search.split_once(':')?;
+        let field = |code: &str| match code.to_ascii_uppercase().as_str() {
+            "T" => Some("Title"),
+            "U" => Some("UserName"),
+            "P" => Some("Password"),
+            "A" => Some("URL"),
+            "N" => Some("Notes"),
+            _ => None,
+        };
+        let contains = |haystack: &str| haystack.to_lowercase().contains(&text.to_lowercase());
+
+        let search_in = search_in.to_ascii_uppercase();
+        let target = self.entries.iter().find(|entry| match search_in.as_str() {
+            "I" => Uuid::parse_str(text).is_ok_and(|uuid| uuid == entry.uuid),
+            "O" => entry
+                .fields
+                .iter()
+                .filter(|(name, _)| !STANDARD_FIELDS.contains(&name.as_str()))
+                .any(|(name, _)| entry.get(name).is_some_and(contains)),
+            code => field(code).is_some_and(|name| entry.get(name).is_some_and(contains)),
+        })?;
+
+        if wanted.eq_ignore_ascii_case("I") {
+            return Some(Zeroizing::new(target.uuid.simple().to_string().to_uppercase()));
+        }
+        let name = field(wanted)?;
+        Some(Zeroizing::new(target.get(name).unwrap_or_default().to_string()))
+    }
+}
+
+/// What to compare and how to pair entries up. The defaults match entries by UUID, compare groups
+/// and leave out the recycle bin, history and usage metadata.
+#[derive(Clone)]
+pub struct CompareOptions {
+    pub match_strategy: MatchStrategy,
+    /// Field whose value identifies entries under `MatchStrategy::ByField`
+    pub match_field: String,
+    pub include_recycle_bin: bool,
+    /// Report groups found in only one database and groups whose settings differ
+    pub compare_groups: bool,
+    pub compare_history: bool,
+    /// Last access time and usage count, which change on every use and so are off by default
+    pub compare_usage_metadata: bool,
+    pub ignore_trailing_whitespace: bool,
+    /// Tell a standard field missing from an entry apart from one that is present but blank; by
+    /// default both read as empty. The keepass crate drops blank fields when reading KDBX files, so
+    /// this only sees the difference in KeePass 1.x databases and databases built in memory
+    pub strict_presence: bool,
+    /// Names of the groups leading to the only subtree compared; empty to compare everything
+    pub subtree: Vec<String>,
+    /// Custom fields expected to differ, such as per-device settings
+    pub ignored_fields: Vec<String>,
+    /// Patterns for groups left out with everything below them, such as templates or archives; see
+    /// `is_excluded`
+    pub excluded_groups: Vec<String>,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            match_strategy: MatchStrategy::ByUuid,
+            match_field: String::new(),
+            include_recycle_bin: false,
+            compare_groups: true,
+            compare_history: false,
+            compare_usage_metadata: false,
+            ignore_trailing_whitespace: false,
+            strict_presence: false,
+            subtree: Vec::new(),
+            ignored_fields: Vec::new(),
+            excluded_groups: Vec::new(),
+        }
+    }
+}
+
+impl CompareOptions {
+    pub fn merge_databases(&self, db1: &Database, db2: &Database, resolution: &HashMap<EntryId, Resolution>) -> Database {
+        // Start from db1 so its configuration and structure carry over
+        let mut merged = db1.clone();
+        if !matches!(merged.config.version, DatabaseVersion::KDB4(_)) {
+            // Only KDBX 4 can be written, so legacy databases are upgraded on save
+            merged.config = DatabaseConfig::default();
+        }
+
+        let (entries1, entries2) = self.paired_entries(db1, db2);
+        let resolution_for = |key: &EntryId| resolution.get(key).copied().unwrap_or_default();
+
+        for (key, found2) in &entries2 {
+            let found1 = entries1.get(key);
+            match merge_action(resolution_for(key), found1, Some(found2)) {
+                MergeAction::Overwrite => {
+                    if let Some(entry) = found1.and_then(|found1| find_entry_mut(&mut merged.root, found1.entry)) {
+                        *entry = Entry {
+                            uuid: entry.uuid,
+                            ..found2.entry.clone()
+                        };
+                    }
+                }
+                MergeAction::Add => {
+                    // Entry only in db2 - add it under the same group path
+                    let group = ensure_group_path(&mut merged.root, &db2.root, &found2.group_path);
+                    group.add_child(found2.entry.clone());
+                }
+                _ => {}
+            }
+        }
+
+        // Entries only in db1 are already present and only go away when db2's side is chosen
+        for (key, found1) in &entries1 {
+            if !entries2.contains_key(key) && merge_action(resolution_for(key), Some(found1), None) == MergeAction::Remove {
+                remove_entry(&mut merged.root, found1.entry);
+            }
+        }
+
+        merged
+    }
+
+    /// What `merge_databases` would do with every entry, without merging anything. Entries without
+    /// any of `differences` are reported as identical rather than by their resolution.
+    pub fn merge_plan(
+        &self,
+        db1: &Database,
+        db2: &Database,
+        resolution: &HashMap<EntryId, Resolution>,
+        differences: &[Difference],
+    ) -> Vec<PlannedAction> {
+        let differing: HashSet<&str> = differences.iter().map(|diff| diff.key.as_str()).collect();
+        let (entries1, entries2) = self.paired_entries(db1, db2);
+        let keys: HashSet<&String> = entries1.keys().chain(entries2.keys()).collect();
+        let mut plan: Vec<PlannedAction> = keys
+            .into_iter()
+            .map(|key| {
+                let (found1, found2) = (entries1.get(key), entries2.get(key));
+                let action = if differing.contains(key.as_str()) {
+                    merge_action(resolution.get(key).copied().unwrap_or_default(), found1, found2)
+                } else {
+                    MergeAction::Identical
+                };
+                let found = found1.or(found2).expect("every key comes from one of the databases");
+                PlannedAction {
+                    title: display_title(found.entry.get_title()),
+                    group_path: found.group_path.join("/"),
+                    action,
+                }
+            })
+            .collect();
+        plan.sort_by(|a, b| (a.action, &a.group_path, &a.title).cmp(&(b.action, &b.group_path, &b.title)));
+        plan
+    }
+
+    /// The entries paired up across `db1` and `db2` that none of `differences` is about, sorted by
+    /// group path and title. Titles and usernames come from `db2`, which may be a hashed snapshot's
+    /// current database.
+    pub fn identical_entries(&self, db1: &Database, db2: &Database, differences: &[Difference]) -> Vec<IdenticalEntry> {
+        let differing: HashSet<&str> = differences.iter().map(|diff| diff.key.as_str()).collect();
+        let (entries1, entries2) = self.paired_entries(db1, db2);
+        let mut identical: Vec<IdenticalEntry> = entries2
+            .iter()
+            .filter(|(key, _)| entries1.contains_key(*key) && !differing.contains(key.as_str()))
+            .map(|(_, found)| IdenticalEntry {
+                title: display_title(found.entry.get_title()),
+                username: found.entry.get_username().unwrap_or_default().to_string(),
+                group_path: found.group_path.join("/"),
+            })
+            .collect();
+        identical.sort_by(|a, b| (&a.group_path, &a.title).cmp(&(&b.group_path, &b.title)));
+        identical
+    }
+
+    /// Like [`compare()`](crate::compare()), calling `progress` with the entries compared so far and
+    /// their total, and stopping early with a partial result once `cancel` is set.
+    pub fn compare_databases(
+        &self,
+        db1: &Database,
+        db2: &Database,
+        cancel: &AtomicBool,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> Vec<Difference> {
+        let now = Utc::now().naive_utc();
+
+        // Build maps of entries keyed according to the match strategy
+        let (entries1, entries2) = self.paired_entries(db1, db2);
+        let lookups1 = DatabaseLookups::of(db1);
+        let lookups2 = DatabaseLookups::of(db2);
+
+        // Check entries in db1
+        let done = AtomicUsize::new(0);
+        let total = entries1.len();
+        let mut differences: Vec<Difference> = entries1
+            .par_iter()
+            .flat_map_iter(|(key, found1)| {
+                // Skip the remaining entries; the caller throws the partial result away
+                if cancel.load(AtomicOrdering::Relaxed) {
+                    return Vec::new();
+                }
+                progress(done.fetch_add(1, AtomicOrdering::Relaxed) + 1, total);
+                self.compare_entry(key, found1, entries2.get(key), now, &lookups1, &lookups2)
+            })
+            .collect();
+
+        // Check for entries only in db2
+        for (key, found2) in &entries2 {
+            if !entries1.contains_key(key) {
+                let entry2 = found2.entry;
+                let title = display_title(entry2.get_title());
+                let username = entry2.get_username().map(|v| v.to_string()).unwrap_or_default();
+
+                differences.push(Difference {
+                    key: key.clone(),
+                    title,
+                    username,
+                    diff_type: DifferenceType::OnlyInTwo,
+                    modified1: None,
+                    modified2: entry2.times.get_last_modification().copied(),
+                    in_recycle_bin: found2.in_recycle_bin,
+                    expired: is_expired(entry2, now),
+                    group_path: found2.group_path.join("/"),
+                    snapshot1: None,
+                    snapshot2: Some(EntrySnapshot::of(entry2)),
+                });
+            }
+        }
+
+        if self.compare_groups {
+            differences.extend(self.compare_group_structure(db1, db2));
+        }
+
+        // HashMap iteration order varies between runs, so settle on title order for exports
+        differences.sort_by_cached_key(|diff| (diff.title.to_lowercase(), diff.key.clone()));
+        differences
+    }
+
+    /// Differences between one entry of the first database and its match in the second, if any.
+    /// Runs on several threads at once, so it only reads shared state.
+    fn compare_entry(
+        &self,
+        key: &str,
+        found1: &EntryRef,
+        found2: Option<&EntryRef>,
+        now: NaiveDateTime,
+        lookups1: &DatabaseLookups,
+        lookups2: &DatabaseLookups,
+    ) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        let entry1 = found1.entry;
+        if let Some(found2) = found2 {
+            let entry2 = found2.entry;
+            // Entry exists in both - check for differences
+            let title = display_title(entry1.get_title());
+
+            // Values are compared with their field references resolved. A reference that points at
+            // nothing in only one database is reported on its own, as its value there is unknown.
+            let mut dangling = Vec::new();
+            let mut resolve = |field: &str| {
+                let resolved1 = lookups1.references.resolve(entry1.get(field).unwrap_or_default());
+                let resolved2 = lookups2.references.resolve(entry2.get(field).unwrap_or_default());
+                let one_sided = match (resolved1.dangling.first(), resolved2.dangling.first()) {
+                    (Some(reference), None) => Some((reference.clone(), true)),
+                    (None, Some(reference)) => Some((reference.clone(), false)),
+                    _ => None,
+                };
+                let comparable = one_sided.is_none();
+                if let Some((reference, dangling_in_first)) = one_sided {
+                    dangling.push(DifferenceType::DanglingReference {
+                        field: field.to_string(),
+                        reference,
+                        dangling_in_first,
+                    });
+                }
+                (resolved1.value, resolved2.value, comparable)
+            };
+
+            let (title1, title2, titles_comparable) = resolve("Title");
+            let (title1, title2) = (title1.to_string(), title2.to_string());
+            let (username1, username2, usernames_comparable) = resolve("UserName");
+            let (username1, username2) = (username1.to_string(), username2.to_string());
+            let (pass1, pass2, passwords_comparable) = resolve("Password");
+            let (url1, url2, urls_comparable) = resolve("URL");
+            let (url1, url2) = (url1.to_string(), url2.to_string());
+            let (notes1, notes2, notes_comparable) = resolve("Notes");
+            let (notes1, notes2) = (notes1.to_string(), notes2.to_string());
+
+            let snapshot1 = EntrySnapshot::of(entry1);
+            let snapshot2 = EntrySnapshot::of(entry2);
+            let difference = |diff_type| Difference {
+                key: key.to_string(),
+                title: title.clone(),
+                username: username1.clone(),
+                diff_type,
+                modified1: entry1.times.get_last_modification().copied(),
+                modified2: entry2.times.get_last_modification().copied(),
+                in_recycle_bin: found1.in_recycle_bin || found2.in_recycle_bin,
+                expired: is_expired(entry1, now) || is_expired(entry2, now),
+                group_path: found1.group_path.join("/"),
+                snapshot1: Some(snapshot1.clone()),
+                snapshot2: Some(snapshot2.clone()),
+            };
+
+            if found1.group_path != found2.group_path {
+                let path = |found: &EntryRef| found.group_path.iter().map(|name| name.to_string()).collect();
+                differences.push(difference(DifferenceType::EntryMoved {
+                    from_path: path(found1),
+                    to_path: path(found2),
+                }));
+            }
+
+            if titles_comparable && !self.values_match(&title1, &title2) {
+                differences.push(difference(DifferenceType::TitleDiffers {
+                    title1,
+                    title2,
+                }));
+            }
+
+            if urls_comparable && !self.values_match(&url1, &url2) {
+                differences.push(difference(DifferenceType::UrlDiffers { url1, url2 }));
+            }
+
+            if notes_comparable && !self.values_match(&notes1, &notes2) {
+                differences.push(difference(DifferenceType::NotesDiffers { notes1, notes2 }));
+            }
+
+            if !usernames_comparable {
+                // Reported as a dangling reference; passwords are only compared once usernames match
+            } else if !self.values_match(&username1, &username2) {
+                differences.push(difference(DifferenceType::UsernameDiffers {
+                    username1: username1.clone(),
+                    username2,
+                }));
+            } else if passwords_comparable && !self.values_match(&pass1, &pass2) {
+                // Passwords built from the entry's own title or username are easy to guess
+                let hints: Vec<&str> = [entry1, entry2]
+                    .iter()
+                    .flat_map(|entry| [entry.get_title(), entry.get_username()])
+                    .flatten()
+                    .collect();
+                differences.push(difference(DifferenceType::PasswordDiffers {
+                    strength1: password_strength(&pass1, &hints),
+                    strength2: password_strength(&pass2, &hints),
+                    password1: pass1,
+                    password2: pass2,
+                }));
+            }
+
+            let expiry1 = expiry_time(entry1);
+            let expiry2 = expiry_time(entry2);
+            if expiry1 != expiry2 {
+                differences.push(difference(DifferenceType::ExpiryDiffers {
+                    expiry1: describe_expiry(expiry1),
+                    expiry2: describe_expiry(expiry2),
+                }));
+            }
+
+            let tags1 = tag_set(entry1);
+            let tags2 = tag_set(entry2);
+            if tags1 != tags2 {
+                differences.push(difference(DifferenceType::TagsDiffers {
+                    added: tags2.difference(&tags1).cloned().collect(),
+                    removed: tags1.difference(&tags2).cloned().collect(),
+                }));
+            }
+
+            let attachments1 = attachment_hashes(entry1);
+            let attachments2 = attachment_hashes(entry2);
+            if attachments1 != attachments2 {
+                let only_in = |these: &BTreeMap<&str, String>, others: &BTreeMap<&str, String>| {
+                    these.keys().filter(|name| !others.contains_key(*name)).map(|name| name.to_string()).collect()
+                };
+                differences.push(difference(DifferenceType::AttachmentsDiffer {
+                    added: only_in(&attachments2, &attachments1),
+                    removed: only_in(&attachments1, &attachments2),
+                    changed: attachments1
+                        .iter()
+                        .filter(|(name, hash)| attachments2.get(*name).is_some_and(|other| other != *hash))
+                        .map(|(name, _)| name.to_string())
+                        .collect(),
+                }));
+            }
+
+            if self.compare_usage_metadata {
+                let usage_count1 = entry1.times.usage_count;
+                let usage_count2 = entry2.times.usage_count;
+                let last_access1 = entry1.times.get_last_access().copied();
+                let last_access2 = entry2.times.get_last_access().copied();
+                if (usage_count1, last_access1) != (usage_count2, last_access2) {
+                    differences.push(difference(DifferenceType::MetadataDiffers {
+                        usage_count1,
+                        usage_count2,
+                        last_access1,
+                        last_access2,
+                    }));
+                }
+            }
+
+            if self.compare_history {
+                let (count1, latest1) = history_summary(entry1);
+                let (count2, latest2) = history_summary(entry2);
+                if (count1, latest1) != (count2, latest2) {
+                    differences.push(difference(DifferenceType::HistoryDiffers {
+                        count1,
+                        count2,
+                        latest1,
+                        latest2,
+                    }));
+                }
+            }
+
+            let autotype1 = AutoTypeSettings::of(entry1);
+            let autotype2 = AutoTypeSettings::of(entry2);
+            if autotype1 != autotype2 {
+                differences.push(difference(DifferenceType::AutoTypeDiffers { autotype1, autotype2 }));
+            }
+
+            let icon1 = EntryIcon::of(entry1, &lookups1.custom_icons);
+            let icon2 = EntryIcon::of(entry2, &lookups2.custom_icons);
+            if icon1 != icon2 {
+                differences.push(difference(DifferenceType::IconDiffers { icon1, icon2 }));
+            }
+
+            for field in self.protection_differences(entry1, entry2) {
+                differences.push(difference(field));
+            }
+
+            for field in self.presence_differences(entry1, entry2) {
+                differences.push(difference(field));
+            }
+
+            let otp1 = OtpConfig::from_entry(entry1);
+            let otp2 = OtpConfig::from_entry(entry2);
+            if otp1 != otp2 {
+                differences.push(difference(DifferenceType::TotpDiffers { otp1, otp2 }));
+            }
+
+            for field in self.custom_field_names(entry1, entry2) {
+                let (value1, value2, comparable) = resolve(field);
+                let present1 = entry1.fields.contains_key(field);
+                let present2 = entry2.fields.contains_key(field);
+
+                if comparable && (!self.values_match(&value1, &value2) || present1 != present2) {
+                    let protected = [entry1, entry2]
+                        .iter()
+                        .any(|e| matches!(e.fields.get(field), Some(Value::Protected(_))));
+                    differences.push(difference(DifferenceType::CustomFieldDiffers {
+                        field: field.to_string(),
+                        value1,
+                        value2,
+                        protected,
+                    }));
+                }
+            }
+
+            differences.extend(dangling.into_iter().map(difference));
+        } else {
+            // Entry only in db1
+            let title = display_title(entry1.get_title());
+            let username = entry1.get_username().map(|v| v.to_string()).unwrap_or_default();
+
+            differences.push(Difference {
+                key: key.to_string(),
+                title,
+                username,
+                diff_type: DifferenceType::OnlyInOne,
+                modified1: entry1.times.get_last_modification().copied(),
+                modified2: None,
+                in_recycle_bin: found1.in_recycle_bin,
+                expired: is_expired(entry1, now),
+                group_path: found1.group_path.join("/"),
+                snapshot1: Some(EntrySnapshot::of(entry1)),
+                snapshot2: None,
+            });
+        }
+        differences
+    }
+
+    /// Both group hierarchies for browsing, with every entry linked to the key of its differences.
+    pub fn explorer_trees(&self, db1: &Database, db2: &Database, differences: &[Difference]) -> [ExplorerGroup; 2] {
+        let differing: HashSet<&str> = differences.iter().map(|diff| diff.key.as_str()).collect();
+        let (entries1, entries2) = self.paired_entries(db1, db2);
+        let keys = |entries: &HashMap<String, EntryRef>| -> HashMap<Uuid, String> {
+            entries.iter().map(|(key, found)| (found.entry.uuid, key.clone())).collect()
+        };
+        [
+            explorer_group(&db1.root, &keys(&entries1), &differing),
+            explorer_group(&db2.root, &keys(&entries2), &differing),
+        ]
+    }
+
+    /// Places every entry of every database in a row, marking where it is missing or differs from the first database.
+    /// `sources` names `reference` followed by `others`.
+    pub fn drift_matrix(&self, reference: &Database, others: &[&Database], sources: Vec<String>, cancel: &AtomicBool) -> DriftMatrix {
+        let reference_entries = self.collect_all_entries(reference);
+        let columns: Vec<(HashMap<String, EntryRef>, HashSet<String>)> = others
+            .iter()
+            .map(|db| {
+                let differing = self
+                    .compare_databases(reference, db, cancel, &|_, _| {})
+                    .into_iter()
+                    .map(|diff| diff.key)
+                    .collect();
+                (self.paired_entries(reference, db).1, differing)
+            })
+            .collect();
+
+        let mut keys: Vec<&String> = reference_entries.keys().collect();
+        for (entries, _) in &columns {
+            keys.extend(entries.keys().filter(|key| !reference_entries.contains_key(*key)));
+        }
+        keys.sort();
+        keys.dedup();
+
+        let mut rows: Vec<DriftRow> = keys
+            .into_iter()
+            .filter_map(|key| {
+                let mut states = vec![if reference_entries.contains_key(key) {
+                    DriftState::Matches
+                } else {
+                    DriftState::Missing
+                }];
+                states.extend(columns.iter().map(|(entries, differing)| {
+                    if !entries.contains_key(key) {
+                        DriftState::Missing
+                    } else if differing.contains(key) {
+                        DriftState::Differs
+                    } else {
+                        DriftState::Matches
+                    }
+                }));
+                if states.iter().all(|state| *state == DriftState::Matches) {
+                    return None;
+                }
+
+                let found = reference_entries
+                    .get(key)
+                    .or_else(|| columns.iter().find_map(|(entries, _)| entries.get(key)))?;
+                Some(DriftRow {
+                    title: display_title(found.entry.get_title()),
+                    group_path: found.group_path.join("/"),
+                    states,
+                })
+            })
+            .collect();
+        rows.sort_by(|a, b| (&a.group_path, &a.title).cmp(&(&b.group_path, &b.title)));
+
+        DriftMatrix { sources, rows }
+    }
+
+    /// Groups found in only one database, and changed settings of groups in both. When matching by
+    /// UUID, a group whose UUID is missing from the other database is matched by its path instead.
+    fn compare_group_structure(&self, db1: &Database, db2: &Database) -> Vec<Difference> {
+        let groups1 = self.collect_all_groups(db1);
+        let mut groups2 = self.collect_all_groups(db2);
+        if self.match_strategy == MatchStrategy::ByUuid {
+            // Databases created independently share no group UUIDs, so pair the rest up by path
+            let path = |found: &GroupRef<'_>| {
+                let mut path = found.parent_path.clone();
+                path.push(found.group.get_name());
+                path.join("/")
+            };
+            let unmatched2: HashMap<String, String> = groups2
+                .iter()
+                .filter(|(key, _)| !groups1.contains_key(*key))
+                .map(|(key, found)| (path(found), key.clone()))
+                .collect();
+            for (key1, found1) in &groups1 {
+                if groups2.contains_key(key1) {
+                    continue;
+                }
+                if let Some(found2) = unmatched2.get(&path(found1)).and_then(|key2| groups2.remove(key2)) {
+                    groups2.insert(key1.clone(), found2);
+                }
+            }
+        }
+
+        let only_in = |groups: &HashMap<String, GroupRef>, others: &HashMap<String, GroupRef>, diff_type: DifferenceType| {
+            groups
+                .iter()
+                .filter(|(key, _)| !others.contains_key(*key))
+                .map(|(key, found)| Difference {
+                    // Keep group keys apart from entry keys, which share the resolution map
+                    key: format!("group:{}", key),
+                    title: found.group.get_name().to_string(),
+                    username: String::new(),
+                    diff_type: diff_type.clone(),
+                    modified1: None,
+                    modified2: None,
+                    in_recycle_bin: found.in_recycle_bin,
+                    expired: false,
+                    group_path: found.parent_path.join("/"),
+                    snapshot1: None,
+                    snapshot2: None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut differences = only_in(&groups1, &groups2, DifferenceType::GroupOnlyInOne);
+        differences.extend(only_in(&groups2, &groups1, DifferenceType::GroupOnlyInTwo));
+
+        for (key, found1) in &groups1 {
+            let Some(found2) = groups2.get(key) else {
+                continue;
+            };
+            let metadata2 = group_metadata(found2.group);
+            for ((field, value1), (_, value2)) in group_metadata(found1.group).into_iter().zip(metadata2) {
+                if self.values_match(&value1, &value2) {
+                    continue;
+                }
+                differences.push(Difference {
+                    key: format!("group:{}", key),
+                    title: found1.group.get_name().to_string(),
+                    username: String::new(),
+                    diff_type: DifferenceType::GroupMetadataDiffers { field: field.to_string(), value1, value2 },
+                    modified1: None,
+                    modified2: None,
+                    in_recycle_bin: found1.in_recycle_bin || found2.in_recycle_bin,
+                    expired: false,
+                    group_path: found1.parent_path.join("/"),
+                    snapshot1: None,
+                    snapshot2: None,
+                });
+            }
+        }
+        differences
+    }
+
+    /// Compares two field values, ignoring trailing whitespace on every line when asked to.
+    fn values_match(&self, value1: &str, value2: &str) -> bool {
+        if !self.ignore_trailing_whitespace {
+            return value1 == value2;
+        }
+        // `lines` also drops the `\r` of CRLF endings
+        value1.trim_end().lines().map(str::trim_end).eq(value2.trim_end().lines().map(str::trim_end))
+    }
+
+    /// Fields present in both entries but memory-protected in only one of them.
+    fn protection_differences(&self, entry1: &Entry, entry2: &Entry) -> Vec<DifferenceType> {
+        let mut fields: Vec<(&String, bool)> = entry1
+            .fields
+            .iter()
+            .filter(|(name, _)| !self.ignored_fields.contains(name))
+            .filter_map(|(name, value1)| match (value1, entry2.fields.get(name)?) {
+                (Value::Protected(_), Value::Unprotected(_)) => Some((name, true)),
+                (Value::Unprotected(_), Value::Protected(_)) => Some((name, false)),
+                _ => None,
+            })
+            .collect();
+        fields.sort();
+        fields
+            .into_iter()
+            .map(|(name, protected1)| DifferenceType::ProtectionDiffers { field: name.clone(), protected1 })
+            .collect()
+    }
+
+    /// Standard fields blank in both entries but missing from only one of them, when presence counts.
+    /// Fields with a value on either side are reported as changed values instead.
+    fn presence_differences(&self, entry1: &Entry, entry2: &Entry) -> Vec<DifferenceType> {
+        if !self.strict_presence {
+            return Vec::new();
+        }
+        let blank = |entry: &Entry, field: &str| entry.get(field).is_none_or(str::is_empty);
+        STANDARD_FIELDS
+            .iter()
+            .filter(|field| !self.ignored_fields.iter().any(|ignored| ignored == *field))
+            .filter(|field| blank(entry1, field) && blank(entry2, field))
+            .filter_map(|field| {
+                let present1 = entry1.fields.contains_key(*field);
+                (present1 != entry2.fields.contains_key(*field))
+                    .then(|| DifferenceType::PresenceDiffers { field: field.to_string(), present1 })
+            })
+            .collect()
+    }
+
+    fn custom_field_names<'a>(&self, entry1: &'a Entry, entry2: &'a Entry) -> Vec<&'a str> {
+        let mut names: Vec<&str> = entry1
+            .fields
+            .keys()
+            .chain(entry2.fields.keys())
+            .map(|k| k.as_str())
+            .filter(|k| !STANDARD_FIELDS.contains(k) && !OTP_FIELDS.contains(k))
+            .filter(|k| !self.ignored_fields.iter().any(|ignored| ignored == k))
+            .filter(|k| ![entry1, entry2].iter().any(|e| matches!(e.fields.get(*k), Some(Value::Bytes(_)))))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Entries keyed by title although matching by field, because they have no value for it.
+    pub fn key_fallbacks(&self, db: &Database) -> usize {
+        if self.match_strategy != MatchStrategy::ByField {
+            return 0;
+        }
+        self.collect_all_entries(db).values().filter(|found| self.match_field_value(found.entry).is_none()).count()
+    }
+
+    fn match_field_value<'a>(&self, entry: &'a Entry) -> Option<&'a str> {
+        entry.get(&self.match_field).filter(|value| !value.is_empty())
+    }
+
+    fn collect_all_entries<'a>(&self, db: &'a Database) -> HashMap<String, EntryRef<'a>> {
+        let mut entries = HashMap::new();
+        for (key, bucket) in self.collect_entry_buckets(db) {
+            for (position, found) in bucket.into_iter().enumerate() {
+                entries.insert(bucket_key(&key, position), found);
+            }
+        }
+        entries
+    }
+
+    /// Like `collect_all_entries` for both databases at once, pairing entries that share a key by username.
+    ///
+    /// The n-th entry of a key in `db1` always gets the same key whatever `db2` holds, so several
+    /// databases can be paired against one reference.
+    fn paired_entries<'a>(
+        &self,
+        db1: &'a Database,
+        db2: &'a Database,
+    ) -> (HashMap<String, EntryRef<'a>>, HashMap<String, EntryRef<'a>>) {
+        let mut buckets1 = self.collect_entry_buckets(db1);
+        let mut buckets2 = self.collect_entry_buckets(db2);
+        let mut keys: Vec<String> = buckets1.keys().chain(buckets2.keys()).cloned().collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut entries1 = HashMap::new();
+        let mut entries2 = HashMap::new();
+        for key in keys {
+            let bucket1 = buckets1.remove(&key).unwrap_or_default();
+            let bucket2 = buckets2.remove(&key).unwrap_or_default();
+            for (position, (found1, found2)) in pair_bucket(bucket1, bucket2).into_iter().enumerate() {
+                let key = bucket_key(&key, position);
+                if let Some(found1) = found1 {
+                    entries1.insert(key.clone(), found1);
+                }
+                if let Some(found2) = found2 {
+                    entries2.insert(key, found2);
+                }
+            }
+        }
+        (entries1, entries2)
+    }
+
+    /// Entries grouped by their match key; several entries share a key when they match by title.
+    fn collect_entry_buckets<'a>(&self, db: &'a Database) -> HashMap<String, Vec<EntryRef<'a>>> {
+        let mut buckets = HashMap::new();
+        if let Some((group, path, in_recycle_bin)) = self.start_group(db) {
+            self.collect_group_entries(group, &path, db.meta.recyclebin_uuid, in_recycle_bin, &mut buckets);
+        }
+        buckets
+    }
+
+    /// Whether the database has the subtree comparing is restricted to, if any.
+    pub fn has_subtree(&self, db: &Database) -> bool {
+        self.start_group(db).is_some()
+    }
+
+    /// The group that comparing starts from: the root, or the chosen subtree along with its path and
+    /// whether it lies in the recycle bin. `None` if the database has no such subtree.
+    fn start_group<'a>(&self, db: &'a Database) -> Option<(&'a Group, Vec<&'a str>, bool)> {
+        let mut group = &db.root;
+        let mut path = Vec::new();
+        let mut in_recycle_bin = false;
+        for name in &self.subtree {
+            group = group.groups().into_iter().find(|child| child.get_name() == name)?;
+            in_recycle_bin |= Some(group.uuid) == db.meta.recyclebin_uuid;
+            path.push(group.get_name());
+        }
+        Some((group, path, in_recycle_bin))
+    }
+
+    fn collect_group_entries<'a>(
+        &self,
+        group: &'a Group,
+        path: &[&'a str],
+        recycle_bin: Option<Uuid>,
+        in_recycle_bin: bool,
+        buckets: &mut HashMap<String, Vec<EntryRef<'a>>>,
+    ) {
+        for entry in group.entries() {
+            let group_path = path.to_vec();
+            buckets.entry(self.entry_key(entry, path)).or_default().push(EntryRef {
+                entry,
+                group_path,
+                in_recycle_bin,
+            });
+        }
+
+        for child_group in group.groups() {
+            // Everything nested below the recycle bin counts as trashed
+            let child_in_recycle_bin = in_recycle_bin || Some(child_group.uuid) == recycle_bin;
+            if child_in_recycle_bin && !self.include_recycle_bin {
+                continue;
+            }
+
+            let mut child_path = path.to_vec();
+            child_path.push(child_group.get_name());
+            if self.is_excluded(&child_path) {
+                continue;
+            }
+            self.collect_group_entries(child_group, &child_path, recycle_bin, child_in_recycle_bin, buckets);
+        }
+    }
+
+    /// Every group below the starting group. The root group itself is never keyed, so the roots of
+    /// two independently created databases always match although their UUIDs differ.
+    fn collect_all_groups<'a>(&self, db: &'a Database) -> HashMap<String, GroupRef<'a>> {
+        match self.start_group(db) {
+            Some((group, path, in_recycle_bin)) => {
+                self.collect_child_groups(group, &path, db.meta.recyclebin_uuid, in_recycle_bin)
+            }
+            None => HashMap::new(),
+        }
+    }
+
+    fn collect_child_groups<'a>(
+        &self,
+        group: &'a Group,
+        path: &[&'a str],
+        recycle_bin: Option<Uuid>,
+        in_recycle_bin: bool,
+    ) -> HashMap<String, GroupRef<'a>> {
+        let mut groups = HashMap::new();
+
+        for child_group in group.groups() {
+            let child_in_recycle_bin = in_recycle_bin || Some(child_group.uuid) == recycle_bin;
+            if child_in_recycle_bin && !self.include_recycle_bin {
+                continue;
+            }
+
+            let mut child_path = path.to_vec();
+            child_path.push(child_group.get_name());
+            if self.is_excluded(&child_path) {
+                continue;
+            }
+
+            let key = match self.match_strategy {
+                MatchStrategy::ByUuid if !child_group.uuid.is_nil() => child_group.uuid.to_string(),
+                _ => child_path.join("/"),
+            };
+            groups.extend(self.collect_child_groups(child_group, &child_path, recycle_bin, child_in_recycle_bin));
+            groups.insert(
+                key,
+                GroupRef {
+                    group: child_group,
+                    parent_path: path.to_vec(),
+                    in_recycle_bin: child_in_recycle_bin,
+                },
+            );
+        }
+
+        groups
+    }
+
+    /// Whether the group at `path` matches an excluded pattern. Patterns containing a slash are
+    /// matched against the whole path from the root, others against the group's name alone.
+    fn is_excluded(&self, path: &[&str]) -> bool {
+        let name = path.last().copied().unwrap_or_default();
+        self.excluded_groups.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_matches(pattern, &path.join("/"))
+            } else {
+                glob_matches(pattern, name)
+            }
+        })
+    }
+
+    fn entry_key(&self, entry: &Entry, path: &[&str]) -> String {
+        // Untitled entries go by username instead, behind a NUL so they can't meet an entry actually
+        // titled "(no title)"; otherwise every untitled entry would land in one bucket.
+        let untitled;
+        let title = match entry.get_title().filter(|title| !title.trim().is_empty()) {
+            Some(title) => title,
+            None => {
+                untitled = format!("\0{}", entry.get_username().unwrap_or_default());
+                untitled.as_str()
+            }
+        };
+        match self.match_strategy {
+            // Fall back to the title for entries without a usable UUID or match field
+            MatchStrategy::ByUuid if !entry.get_uuid().is_nil() => entry.get_uuid().to_string(),
+            MatchStrategy::ByField if self.match_field_value(entry).is_some() => {
+                // The field name keeps values apart from titles used as fallback keys
+                format!("{}\0{}", self.match_field, self.match_field_value(entry).unwrap_or_default())
+            }
+            MatchStrategy::ByUuid | MatchStrategy::ByTitle | MatchStrategy::ByField => String::from(title),
+            MatchStrategy::ByGroupPathAndTitle => {
+                let mut key = path.join("/");
+                key.push('/');
+                key.push_str(title);
+                key
+            }
+        }
+    }
+}
+
+/// What merging does with an entry given its resolution and where it was found.
+fn merge_action(resolution: Resolution, found1: Option<&EntryRef>, found2: Option<&EntryRef>) -> MergeAction {
+    match (found1, found2) {
+        (Some(found1), Some(found2)) => {
+            let keep_second = match resolution {
+                Resolution::KeepNewer => {
+                    found2.entry.times.get_last_modification() > found1.entry.times.get_last_modification()
+                }
+                Resolution::KeepSecond => true,
+                Resolution::KeepFirst | Resolution::Skip => false,
+            };
+            if keep_second {
+                MergeAction::Overwrite
+            } else {
+                MergeAction::KeepFirst
+            }
+        }
+        (None, _) => match resolution {
+            Resolution::KeepNewer | Resolution::KeepSecond => MergeAction::Add,
+            Resolution::KeepFirst | Resolution::Skip => MergeAction::LeaveOut,
+        },
+        (Some(_), None) => match resolution {
+            Resolution::KeepSecond => MergeAction::Remove,
+            _ => MergeAction::KeepFirst,
+        },
+    }
+}
+
+/// Matches `text` against `pattern`, ignoring case, where `*` stands for any run of characters and
+/// `?` for any single one.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => (0..=text.len()).any(|skip| matches(rest, &text[skip..])),
+            Some((&expected, rest)) => text
+                .split_first()
+                .is_some_and(|(&c, text)| (expected == '?' || expected == c) && matches(rest, text)),
+        }
+    }
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    matches(&pattern, &text)
+}
+
+/// A title fit for one line of the UI: control characters such as newlines and tabs become spaces,
+/// and a missing or blank title reads "(no title)".
+pub fn display_title(title: Option<&str>) -> String {
+    let title: String = title.unwrap_or_default().chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    match title.trim() {
+        "" => String::from("(no title)"),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Key of the entry at `position` within a bucket; the first keeps the plain key.
+fn bucket_key(key: &str, position: usize) -> String {
+    if position == 0 {
+        key.to_string()
+    } else {
+        format!("{}#{}", key, position + 1)
+    }
+}
+
+/// Pairs the entries of two same-key buckets, preferring equal usernames and then going by order.
+/// The result keeps `bucket1`'s order, followed by whatever is left of `bucket2`.
+fn pair_bucket<'a>(
+    bucket1: Vec<EntryRef<'a>>,
+    bucket2: Vec<EntryRef<'a>>,
+) -> Vec<(Option<EntryRef<'a>>, Option<EntryRef<'a>>)> {
+    let mut rest2: Vec<Option<EntryRef>> = bucket2.into_iter().map(Some).collect();
+    let mut partners: Vec<Option<EntryRef>> = bucket1
+        .iter()
+        .map(|found1| {
+            let username = found1.entry.get_username();
+            rest2
+                .iter_mut()
+                .find(|found2| found2.as_ref().is_some_and(|found2| found2.entry.get_username() == username))
+                .and_then(Option::take)
+        })
+        .collect();
+
+    let mut rest2 = rest2.into_iter().flatten();
+    for partner in partners.iter_mut().filter(|partner| partner.is_none()) {
+        *partner = rest2.next();
+    }
+
+    bucket1
+        .into_iter()
+        .map(Some)
+        .zip(partners)
+        .chain(rest2.map(|found2| (None, Some(found2))))
+        .collect()
+}
+
+/// The group-level settings compared between matched groups, which entries inherit. Matching by
+/// UUID is the only way a group's name can differ, so it is compared along with them.
+fn group_metadata(group: &Group) -> [(&'static str, String); 5] {
+    // KeePass writes "null" for "inherit from the parent group"
+    let inherited = |setting: &Option<String>| match setting.as_deref() {
+        None | Some("null") => String::from("inherit"),
+        Some(value) => value.to_lowercase(),
+    };
+    [
+        ("Name", group.name.clone()),
+        ("Notes", group.notes.clone().unwrap_or_default()),
+        ("Default auto-type sequence", group.default_autotype_sequence.clone().unwrap_or_default()),
+        ("Auto-type enabled", inherited(&group.enable_autotype)),
+        ("Searching enabled", inherited(&group.enable_searching)),
+    ]
+}
+
+/// Removes the entry sharing `target`'s UUID anywhere below `group`, returning whether there was one.
+fn remove_entry(group: &mut Group, target: &Entry) -> bool {
+    let before = group.children.len();
+    group
+        .children
+        .retain(|node| !matches!(node, Node::Entry(entry) if entry.uuid == target.uuid));
+    if group.children.len() != before {
+        return true;
+    }
+    group.groups_mut().into_iter().any(|child| remove_entry(child, target))
+}
+
+fn find_entry_mut<'a>(group: &'a mut Group, target: &Entry) -> Option<&'a mut Entry> {
+    for node in &mut group.children {
+        let found = match node {
+            Node::Entry(entry) if entry.uuid == target.uuid => Some(entry),
+            Node::Entry(_) => None,
+            Node::Group(child) => find_entry_mut(child, target),
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// Walks `path` from `target`, creating any missing groups as copies of their counterparts in `source`.
+fn ensure_group_path<'a>(target: &'a mut Group, source: &Group, path: &[&str]) -> &'a mut Group {
+    let Some((name, rest)) = path.split_first() else {
+        return target;
+    };
+
+    let fallback = Group::new(name);
+    let source_child = source
+        .groups()
+        .into_iter()
+        .find(|g| g.get_name() == *name)
+        .unwrap_or(&fallback);
+
+    let position = match target.groups().iter().position(|g| g.get_name() == *name) {
+        Some(position) => position,
+        None => {
+            target.add_child(Group {
+                children: Vec::new(),
+                ..source_child.clone()
+            });
+            target.groups().len() - 1
+        }
+    };
+
+    let child = target.groups_mut().swap_remove(position);
+    ensure_group_path(child, source_child, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difference::DifferenceTypeKind;
+    use crate::test_support::{entry_mut, DatabaseBuilder, EntryBuilder, GroupBuilder};
+    use keepass::db::{AutoType, History};
+
+    fn sample() -> Database {
+        DatabaseBuilder::new()
+            .entry(EntryBuilder::new("Mail").username("alice").password("hunter2").url("https://mail.example"))
+            .group(
+                GroupBuilder::new("Work")
+                    .entry(EntryBuilder::new("VPN").username("alice").password("s3cret").field("Server", "vpn1")),
+            )
+            .build()
+    }
+
+    fn differences(db1: &Database, db2: &Database, options: CompareOptions) -> Vec<Difference> {
+        options.compare_databases(db1, db2, &AtomicBool::new(false), &|_, _| {})
+    }
+
+    fn kinds(differences: &[Difference]) -> Vec<DifferenceTypeKind> {
+        differences.iter().map(|diff| diff.diff_type.kind()).collect()
+    }
+
+    /// The differences after `change` is applied to a copy of the sample database.
+    fn after(change: impl FnOnce(&mut Database)) -> Vec<Difference> {
+        after_with(CompareOptions::default(), change)
+    }
+
+    fn after_with(options: CompareOptions, change: impl FnOnce(&mut Database)) -> Vec<Difference> {
+        let db1 = sample();
+        let mut db2 = db1.clone();
+        change(&mut db2);
+        differences(&db1, &db2, options)
+    }
+
+    fn set(db: &mut Database, title: &str, field: &str, value: &str) {
+        entry_mut(db, title).fields.insert(field.to_string(), Value::Unprotected(value.to_string()));
+    }
+
+    fn work_group(db: &mut Database) -> &mut Group {
+        db.root.groups_mut().into_iter().find(|group| group.name == "Work").expect("the sample has a Work group")
+    }
+
+    #[test]
+    fn progress_reaches_the_total() {
+        let db = sample();
+        let furthest = AtomicUsize::new(0);
+        CompareOptions::default().compare_databases(&db, &db, &AtomicBool::new(false), &|done, total| {
+            assert_eq!(total, 2);
+            furthest.fetch_max(done, AtomicOrdering::Relaxed);
+        });
+        assert_eq!(furthest.into_inner(), 2);
+    }
+
+    #[test]
+    fn only_in_one() {
+        let db2 = sample();
+        let mut db1 = db2.clone();
+        db1.root.add_child(EntryBuilder::new("Bank").build());
+        let found = differences(&db1, &db2, CompareOptions::default());
+        assert_eq!(kinds(&found), [DifferenceTypeKind::OnlyInOne]);
+        assert_eq!(found[0].title, "Bank");
+    }
+
+    #[test]
+    fn only_in_two() {
+        let found = after(|db| db.root.add_child(EntryBuilder::new("Bank").build()));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::OnlyInTwo]);
+        assert_eq!(found[0].title, "Bank");
+    }
+
+    #[test]
+    fn title_differs() {
+        let found = after(|db| set(db, "Mail", "Title", "Webmail"));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::TitleDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::TitleDiffers { title1, title2 }
+            if title1 == "Mail" && title2 == "Webmail"));
+    }
+
+    #[test]
+    fn username_differs() {
+        let found = after(|db| set(db, "Mail", "UserName", "bob"));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::UsernameDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::UsernameDiffers { username1, username2 }
+            if username1 == "alice" && username2 == "bob"));
+    }
+
+    #[test]
+    fn password_differs() {
+        let found = after(|db| {
+            entry_mut(db, "Mail").fields.insert(String::from("Password"), Value::Protected("changed".as_bytes().into()));
+        });
+        assert_eq!(kinds(&found), [DifferenceTypeKind::PasswordDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::PasswordDiffers { password1, password2, .. }
+            if password1.as_str() == "hunter2" && password2.as_str() == "changed"));
+    }
+
+    #[test]
+    fn url_differs() {
+        let found = after(|db| set(db, "Mail", "URL", "https://mail.example.org"));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::UrlDiffers]);
+    }
+
+    #[test]
+    fn notes_differ() {
+        let found = after(|db| set(db, "Mail", "Notes", "Recovery codes are in the safe"));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::NotesDiffers]);
+    }
+
+    #[test]
+    fn custom_field_differs() {
+        let found = after(|db| set(db, "VPN", "Server", "vpn2"));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::CustomFieldDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::CustomFieldDiffers { field, value1, value2, protected: false }
+            if field == "Server" && value1.as_str() == "vpn1" && value2.as_str() == "vpn2"));
+    }
+
+    #[test]
+    fn totp_differs() {
+        let found = after(|db| set(db, "Mail", "otp", "otpauth://totp/Mail?secret=JBSWY3DPEHPK3PXP"));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::TotpDiffers]);
+    }
+
+    #[test]
+    fn group_only_in_one() {
+        let db2 = sample();
+        let mut db1 = db2.clone();
+        db1.root.add_child(Group::new("Archive"));
+        let found = differences(&db1, &db2, CompareOptions::default());
+        assert_eq!(kinds(&found), [DifferenceTypeKind::GroupOnlyInOne]);
+        assert_eq!(found[0].title, "Archive");
+    }
+
+    #[test]
+    fn group_only_in_two() {
+        let found = after(|db| db.root.add_child(Group::new("Archive")));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::GroupOnlyInTwo]);
+        assert_eq!(found[0].title, "Archive");
+    }
+
+    #[test]
+    fn entry_moved() {
+        let found = after(|db| {
+            let mail = entry_mut(db, "Mail").clone();
+            db.root.children.retain(|node| !matches!(node, Node::Entry(entry) if entry.uuid == mail.uuid));
+            work_group(db).add_child(mail);
+        });
+        assert_eq!(kinds(&found), [DifferenceTypeKind::EntryMoved]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::EntryMoved { from_path, to_path }
+            if from_path.is_empty() && to_path == &["Work"]));
+    }
+
+    #[test]
+    fn expiry_differs() {
+        let found = after(|db| {
+            let times = &mut entry_mut(db, "Mail").times;
+            times.expires = true;
+            times.set_expiry(NaiveDateTime::parse_from_str("2030-01-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap());
+        });
+        assert_eq!(kinds(&found), [DifferenceTypeKind::ExpiryDiffers]);
+    }
+
+    #[test]
+    fn tags_differ() {
+        let found = after(|db| entry_mut(db, "Mail").tags = vec![String::from("personal")]);
+        assert_eq!(kinds(&found), [DifferenceTypeKind::TagsDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::TagsDiffers { added, removed }
+            if added == &["personal"] && removed.is_empty()));
+    }
+
+    #[test]
+    fn attachments_differ() {
+        let found = after(|db| {
+            entry_mut(db, "Mail").fields.insert(String::from("codes.txt"), Value::Bytes(b"123456".to_vec()));
+        });
+        assert_eq!(kinds(&found), [DifferenceTypeKind::AttachmentsDiffer]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::AttachmentsDiffer { added, removed, changed }
+            if added == &["codes.txt"] && removed.is_empty() && changed.is_empty()));
+    }
+
+    #[test]
+    fn history_differs_only_when_compared() {
+        let add_version = |db: &mut Database| {
+            let entry = entry_mut(db, "Mail");
+            let mut history = History::default();
+            history.add_entry(entry.clone());
+            entry.history = Some(history);
+        };
+        assert!(after(add_version).is_empty());
+        let options = CompareOptions { compare_history: true, ..CompareOptions::default() };
+        assert_eq!(kinds(&after_with(options, add_version)), [DifferenceTypeKind::HistoryDiffers]);
+    }
+
+    #[test]
+    fn icon_differs() {
+        let found = after(|db| entry_mut(db, "Mail").icon_id = Some(12));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::IconDiffers]);
+    }
+
+    #[test]
+    fn metadata_differs_only_when_compared() {
+        let use_entry = |db: &mut Database| entry_mut(db, "Mail").times.usage_count += 3;
+        assert!(after(use_entry).is_empty());
+        let options = CompareOptions { compare_usage_metadata: true, ..CompareOptions::default() };
+        assert_eq!(kinds(&after_with(options, use_entry)), [DifferenceTypeKind::MetadataDiffers]);
+    }
+
+    #[test]
+    fn protection_differs() {
+        let found = after(|db| {
+            entry_mut(db, "VPN").fields.insert(String::from("Server"), Value::Protected("vpn1".as_bytes().into()));
+        });
+        assert_eq!(kinds(&found), [DifferenceTypeKind::ProtectionDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::ProtectionDiffers { field, protected1: false }
+            if field == "Server"));
+    }
+
+    #[test]
+    fn presence_differs_only_when_strict() {
+        let db1 = DatabaseBuilder::new().entry(EntryBuilder::new("Mail").field("Notes", "")).build();
+        let mut db2 = db1.clone();
+        entry_mut(&mut db2, "Mail").fields.remove("Notes");
+        assert!(differences(&db1, &db2, CompareOptions::default()).is_empty());
+        let options = CompareOptions { strict_presence: true, ..CompareOptions::default() };
+        let found = differences(&db1, &db2, options);
+        assert_eq!(kinds(&found), [DifferenceTypeKind::PresenceDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::PresenceDiffers { field, present1: true }
+            if field == "Notes"));
+    }
+
+    #[test]
+    fn presence_of_blank_fields() {
+        // Each side's notes are either absent (None) or present and empty
+        let set_notes = |db: &mut Database, notes: Option<&str>| {
+            let fields = &mut entry_mut(db, "Mail").fields;
+            match notes {
+                Some(notes) => fields.insert(String::from("Notes"), Value::Unprotected(notes.to_string())),
+                None => fields.remove("Notes"),
+            };
+        };
+        let strict = || CompareOptions { strict_presence: true, ..CompareOptions::default() };
+        for (notes1, notes2) in [(None, None), (None, Some("")), (Some(""), None), (Some(""), Some(""))] {
+            let mut db1 = DatabaseBuilder::new().entry(EntryBuilder::new("Mail")).build();
+            let mut db2 = db1.clone();
+            set_notes(&mut db1, notes1);
+            set_notes(&mut db2, notes2);
+
+            assert!(differences(&db1, &db2, CompareOptions::default()).is_empty(), "{:?} {:?}", notes1, notes2);
+            let found = differences(&db1, &db2, strict());
+            if notes1.is_some() == notes2.is_some() {
+                assert!(found.is_empty(), "{:?} {:?}", notes1, notes2);
+            } else {
+                assert_eq!(kinds(&found), [DifferenceTypeKind::PresenceDiffers]);
+                assert!(matches!(&found[0].diff_type, DifferenceType::PresenceDiffers { field, present1 }
+                    if field == "Notes" && *present1 == notes1.is_some()));
+            }
+        }
+    }
+
+    #[test]
+    fn auto_type_differs() {
+        let found = after(|db| {
+            entry_mut(db, "Mail").autotype = Some(AutoType { enabled: false, sequence: None, associations: Vec::new() });
+        });
+        assert_eq!(kinds(&found), [DifferenceTypeKind::AutoTypeDiffers]);
+    }
+
+    #[test]
+    fn group_metadata_differs() {
+        let found = after(|db| work_group(db).notes = Some(String::from("Company accounts")));
+        assert_eq!(kinds(&found), [DifferenceTypeKind::GroupMetadataDiffers]);
+        assert!(matches!(&found[0].diff_type, DifferenceType::GroupMetadataDiffers { field, value2, .. }
+            if field == "Notes" && value2 == "Company accounts"));
+    }
+
+    #[test]
+    fn dangling_reference() {
+        // Both passwords point at an entry that only the first database has
+        let target = EntryBuilder::new("Shared secret").password("from elsewhere").build();
+        let reference = format!("{{REF:P@I:{}}}", target.uuid.simple());
+        let mut db2 = sample();
+        set(&mut db2, "Mail", "Password", &reference);
+        let mut db1 = db2.clone();
+        db1.root.add_child(target);
+
+        let found = differences(&db1, &db2, CompareOptions::default());
+        let mut found_kinds = kinds(&found);
+        found_kinds.sort();
+        assert_eq!(found_kinds, [DifferenceTypeKind::OnlyInOne, DifferenceTypeKind::DanglingReference]);
+        assert!(found.iter().any(|diff| matches!(&diff.diff_type,
+            DifferenceType::DanglingReference { field, dangling_in_first: false, .. } if field == "Password")));
+    }
+
+    fn separately_built() -> Database {
+        DatabaseBuilder::new()
+            .group(GroupBuilder::new("Work").group(GroupBuilder::new("Servers")).entry(EntryBuilder::new("VPN")))
+            .group(GroupBuilder::new("Personal"))
+            .build()
+    }
+
+    #[test]
+    fn separately_built_databases_have_no_group_differences() {
+        let (db1, db2) = (separately_built(), separately_built());
+        assert_ne!(db1.root.uuid, db2.root.uuid);
+        assert!(differences(&db1, &db2, CompareOptions::default()).iter().all(|diff| !diff.diff_type.kind().is_group()));
+        // Groups paired by path still report their own changes
+        let mut db2 = db2;
+        work_group(&mut db2).notes = Some(String::from("Company accounts"));
+        db2.root.add_child(Group::new("Archive"));
+        let mut found = kinds(&differences(&db1, &db2, CompareOptions::default()));
+        found.retain(DifferenceTypeKind::is_group);
+        found.sort();
+        assert_eq!(found, [DifferenceTypeKind::GroupOnlyInTwo, DifferenceTypeKind::GroupMetadataDiffers]);
+    }
+
+    fn shared_mail(first: (&str, &str), second: (&str, &str)) -> Database {
+        let mail = |(username, password): (&str, &str)| EntryBuilder::new("Mail").username(username).password(password);
+        DatabaseBuilder::new().group(GroupBuilder::new("Accounts").entry(mail(first)).entry(mail(second))).build()
+    }
+
+    #[test]
+    fn same_titled_entries_are_paired_by_username() {
+        let options = || CompareOptions {
+            match_strategy: MatchStrategy::ByGroupPathAndTitle,
+            ..CompareOptions::default()
+        };
+        let db1 = shared_mail(("alice", "hunter2"), ("bob", "s3cret"));
+        // Listed the other way round, which pairing by order alone would report as two changed usernames
+        let db2 = shared_mail(("bob", "s3cret"), ("alice", "hunter2"));
+        assert!(differences(&db1, &db2, options()).is_empty());
+
+        let db2 = shared_mail(("bob", "s3cret"), ("alice", "hunter3"));
+        let found = differences(&db1, &db2, options());
+        assert_eq!(kinds(&found), [DifferenceTypeKind::PasswordDiffers]);
+        assert_eq!(found[0].username, "alice");
+    }
+
+    #[test]
+    fn control_characters_in_titles_are_shown_as_spaces() {
+        assert_eq!(display_title(Some("Mail\nWork")), "Mail Work");
+        assert_eq!(display_title(Some("\tMail\tWork\r\n")), "Mail Work");
+        assert_eq!(display_title(Some("\n\t")), "(no title)");
+        assert_eq!(display_title(Some("")), "(no title)");
+        assert_eq!(display_title(None), "(no title)");
+    }
+
+    #[test]
+    fn titles_are_matched_as_written() {
+        let by_title = || CompareOptions { match_strategy: MatchStrategy::ByTitle, ..CompareOptions::default() };
+        let entry = EntryBuilder::new("Mail\nWork").username("alice").build();
+        assert_eq!(by_title().entry_key(&entry, &[]), "Mail\nWork");
+        let untitled = EntryBuilder::new("").username("alice").build();
+        assert_eq!(by_title().entry_key(&untitled, &[]), "\0alice");
+
+        // The cleaned-up title is only for display, so it doesn't match an entry titled that way
+        let db1 = DatabaseBuilder::new().entry(EntryBuilder::new("Mail\nWork").password("hunter2")).build();
+        let db2 = DatabaseBuilder::new().entry(EntryBuilder::new("Mail\nWork").password("hunter3")).build();
+        let found = differences(&db1, &db2, by_title());
+        assert_eq!(kinds(&found), [DifferenceTypeKind::PasswordDiffers]);
+        assert_eq!((found[0].key.as_str(), found[0].title.as_str()), ("Mail\nWork", "Mail Work"));
+        let db2 = DatabaseBuilder::new().entry(EntryBuilder::new("Mail Work").password("hunter2")).build();
+        let found = differences(&db1, &db2, by_title());
+        assert_eq!(kinds(&found), [DifferenceTypeKind::OnlyInOne, DifferenceTypeKind::OnlyInTwo]);
+
+        // Untitled entries go by their usernames rather than all meeting under "(no title)"
+        let untitled = |username: &str, password: &str| EntryBuilder::new("").username(username).password(password);
+        let db1 = DatabaseBuilder::new().entry(untitled("alice", "hunter2")).entry(untitled("bob", "s3cret")).build();
+        let db2 = DatabaseBuilder::new().entry(untitled("bob", "s3cret")).entry(untitled("alice", "hunter3")).build();
+        let found = differences(&db1, &db2, by_title());
+        assert_eq!(kinds(&found), [DifferenceTypeKind::PasswordDiffers]);
+        assert_eq!((found[0].title.as_str(), found[0].username.as_str()), ("(no title)", "alice"));
+    }
+}
@@ -0,0 +1,405 @@
+//! What a comparison finds: one `Difference` per changed field, entry or group, and the kinds they
+//! are tallied and filtered by.
+
+use chrono::NaiveDateTime;
+use keepass::db::Entry;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+use crate::fields::{describe_history, describe_usage, AutoTypeSettings, EntryIcon, OtpConfig};
+
+/// Identifies a matched entry across both databases, as produced by `CompareOptions::entry_key`.
+pub type EntryId = String;
+
+#[derive(Clone, Serialize)]
+pub struct Difference {
+    #[serde(skip)]
+    pub key: String,
+    pub title: String,
+    pub username: String,
+    #[serde(flatten)]
+    pub diff_type: DifferenceType,
+    pub modified1: Option<NaiveDateTime>,
+    pub modified2: Option<NaiveDateTime>,
+    pub in_recycle_bin: bool,
+    /// Past its expiry time in either database, whether or not the expiry itself differs
+    pub expired: bool,
+    pub group_path: String,
+    /// Standard fields of the entry in each database, shared by all of that entry's differences
+    #[serde(skip)]
+    pub snapshot1: Option<Arc<EntrySnapshot>>,
+    #[serde(skip)]
+    pub snapshot2: Option<Arc<EntrySnapshot>>,
+}
+
+/// The standard fields of one entry, for showing both sides of a difference in full.
+pub struct EntrySnapshot {
+    pub title: String,
+    pub username: String,
+    pub url: String,
+    pub notes: String,
+    pub password: Zeroizing<String>,
+}
+
+impl EntrySnapshot {
+    pub fn of(entry: &Entry) -> Arc<Self> {
+        let field = |name: &str| entry.get(name).map(|v| v.to_string()).unwrap_or_default();
+        Arc::new(Self {
+            title: field("Title"),
+            username: field("UserName"),
+            url: field("URL"),
+            notes: field("Notes"),
+            password: Zeroizing::new(field("Password")),
+        })
+    }
+
+    /// Field names paired with values, with the password last.
+    pub fn fields(&self) -> [(&'static str, &str); 5] {
+        [
+            ("Title", &self.title),
+            ("UserName", &self.username),
+            ("URL", &self.url),
+            ("Notes", &self.notes),
+            ("Password", &self.password),
+        ]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Freshness {
+    FirstNewer,
+    SecondNewer,
+    /// Both copies claim the same modification time yet their contents differ
+    Conflict,
+}
+
+impl Difference {
+    /// `filter` must already be lowercase.
+    pub fn matches_filter(&self, filter: &str) -> bool {
+        filter.is_empty()
+            || [&self.title, &self.username, &self.group_path]
+                .iter()
+                .any(|value| value.to_lowercase().contains(filter))
+    }
+
+    pub fn freshness(&self) -> Option<Freshness> {
+        let (modified1, modified2) = (self.modified1?, self.modified2?);
+        Some(match modified1.cmp(&modified2) {
+            Ordering::Greater => Freshness::FirstNewer,
+            Ordering::Less => Freshness::SecondNewer,
+            Ordering::Equal => Freshness::Conflict,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub enum DifferenceType {
+    OnlyInOne,
+    OnlyInTwo,
+    TitleDiffers { title1: String, title2: String },
+    UsernameDiffers { username1: String, username2: String },
+    PasswordDiffers {
+        password1: Zeroizing<String>,
+        password2: Zeroizing<String>,
+        /// zxcvbn scores from 0 (very weak) to 4 (very strong)
+        strength1: u8,
+        strength2: u8,
+    },
+    UrlDiffers { url1: String, url2: String },
+    NotesDiffers { notes1: String, notes2: String },
+    CustomFieldDiffers { field: String, value1: Zeroizing<String>, value2: Zeroizing<String>, protected: bool },
+    TotpDiffers { otp1: Option<OtpConfig>, otp2: Option<OtpConfig> },
+    GroupOnlyInOne,
+    GroupOnlyInTwo,
+    /// The same entry sits under different groups, whether or not any of its fields changed
+    EntryMoved { from_path: Vec<String>, to_path: Vec<String> },
+    ExpiryDiffers { expiry1: String, expiry2: String },
+    TagsDiffers { added: Vec<String>, removed: Vec<String> },
+    AttachmentsDiffer { added: Vec<String>, removed: Vec<String>, changed: Vec<String> },
+    HistoryDiffers { count1: usize, count2: usize, latest1: Option<NaiveDateTime>, latest2: Option<NaiveDateTime> },
+    IconDiffers { icon1: EntryIcon, icon2: EntryIcon },
+    MetadataDiffers { usage_count1: usize, usage_count2: usize, last_access1: Option<NaiveDateTime>, last_access2: Option<NaiveDateTime> },
+    /// A field in both entries is memory-protected in only one of them
+    ProtectionDiffers { field: String, protected1: bool },
+    /// A standard field is blank in both entries but missing altogether from one of them
+    PresenceDiffers { field: String, present1: bool },
+    /// A group in both databases has a different name, notes or entry defaults
+    GroupMetadataDiffers { field: String, value1: String, value2: String },
+    AutoTypeDiffers { autotype1: AutoTypeSettings, autotype2: AutoTypeSettings },
+    /// A field reference finds its target in one database but not in the other, so the values cannot be compared
+    DanglingReference { field: String, reference: String, dangling_in_first: bool },
+}
+
+/// Placeholder written instead of secret values in exports.
+pub const REDACTED: &str = "(changed)";
+
+/// Fieldless mirror of `DifferenceType`, used to filter and tally differences by variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DifferenceTypeKind {
+    OnlyInOne,
+    OnlyInTwo,
+    TitleDiffers,
+    UsernameDiffers,
+    PasswordDiffers,
+    UrlDiffers,
+    NotesDiffers,
+    CustomFieldDiffers,
+    TotpDiffers,
+    GroupOnlyInOne,
+    GroupOnlyInTwo,
+    EntryMoved,
+    ExpiryDiffers,
+    TagsDiffers,
+    AttachmentsDiffer,
+    HistoryDiffers,
+    IconDiffers,
+    MetadataDiffers,
+    ProtectionDiffers,
+    PresenceDiffers,
+    GroupMetadataDiffers,
+    AutoTypeDiffers,
+    DanglingReference,
+}
+
+impl DifferenceTypeKind {
+    pub const ALL: [DifferenceTypeKind; 23] = [
+        DifferenceTypeKind::OnlyInOne,
+        DifferenceTypeKind::OnlyInTwo,
+        DifferenceTypeKind::TitleDiffers,
+        DifferenceTypeKind::UsernameDiffers,
+        DifferenceTypeKind::PasswordDiffers,
+        DifferenceTypeKind::UrlDiffers,
+        DifferenceTypeKind::NotesDiffers,
+        DifferenceTypeKind::CustomFieldDiffers,
+        DifferenceTypeKind::TotpDiffers,
+        DifferenceTypeKind::GroupOnlyInOne,
+        DifferenceTypeKind::GroupOnlyInTwo,
+        DifferenceTypeKind::EntryMoved,
+        DifferenceTypeKind::ExpiryDiffers,
+        DifferenceTypeKind::TagsDiffers,
+        DifferenceTypeKind::AttachmentsDiffer,
+        DifferenceTypeKind::HistoryDiffers,
+        DifferenceTypeKind::IconDiffers,
+        DifferenceTypeKind::MetadataDiffers,
+        DifferenceTypeKind::ProtectionDiffers,
+        DifferenceTypeKind::PresenceDiffers,
+        DifferenceTypeKind::GroupMetadataDiffers,
+        DifferenceTypeKind::AutoTypeDiffers,
+        DifferenceTypeKind::DanglingReference,
+    ];
+
+    pub fn is_group(&self) -> bool {
+        matches!(
+            self,
+            DifferenceTypeKind::GroupOnlyInOne | DifferenceTypeKind::GroupOnlyInTwo | DifferenceTypeKind::GroupMetadataDiffers
+        )
+    }
+
+    /// Whether this kind means the item exists on both sides with diverging values.
+    pub fn is_conflict(&self) -> bool {
+        !matches!(
+            self,
+            DifferenceTypeKind::OnlyInOne
+                | DifferenceTypeKind::OnlyInTwo
+                | DifferenceTypeKind::GroupOnlyInOne
+                | DifferenceTypeKind::GroupOnlyInTwo
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DifferenceTypeKind::OnlyInOne => "only_in_one",
+            DifferenceTypeKind::OnlyInTwo => "only_in_two",
+            DifferenceTypeKind::TitleDiffers => "title_differs",
+            DifferenceTypeKind::UsernameDiffers => "username_differs",
+            DifferenceTypeKind::PasswordDiffers => "password_differs",
+            DifferenceTypeKind::UrlDiffers => "url_differs",
+            DifferenceTypeKind::NotesDiffers => "notes_differs",
+            DifferenceTypeKind::CustomFieldDiffers => "custom_field_differs",
+            DifferenceTypeKind::TotpDiffers => "totp_differs",
+            DifferenceTypeKind::GroupOnlyInOne => "group_only_in_one",
+            DifferenceTypeKind::GroupOnlyInTwo => "group_only_in_two",
+            DifferenceTypeKind::EntryMoved => "moved",
+            DifferenceTypeKind::ExpiryDiffers => "expiry_differs",
+            DifferenceTypeKind::TagsDiffers => "tags_differ",
+            DifferenceTypeKind::AttachmentsDiffer => "attachments_differ",
+            DifferenceTypeKind::HistoryDiffers => "history_differs",
+            DifferenceTypeKind::IconDiffers => "icon_differs",
+            DifferenceTypeKind::MetadataDiffers => "metadata_differs",
+            DifferenceTypeKind::ProtectionDiffers => "protection_differs",
+            DifferenceTypeKind::PresenceDiffers => "presence_differs",
+            DifferenceTypeKind::GroupMetadataDiffers => "group_metadata_differs",
+            DifferenceTypeKind::AutoTypeDiffers => "auto_type_differs",
+            DifferenceTypeKind::DanglingReference => "dangling_reference",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DifferenceTypeKind::OnlyInOne => "Only in database 1",
+            DifferenceTypeKind::OnlyInTwo => "Only in database 2",
+            DifferenceTypeKind::TitleDiffers => "Title differs",
+            DifferenceTypeKind::UsernameDiffers => "Username differs",
+            DifferenceTypeKind::PasswordDiffers => "Password differs",
+            DifferenceTypeKind::UrlDiffers => "URL differs",
+            DifferenceTypeKind::NotesDiffers => "Notes differ",
+            DifferenceTypeKind::CustomFieldDiffers => "Custom field differs",
+            DifferenceTypeKind::TotpDiffers => "TOTP configuration differs",
+            DifferenceTypeKind::GroupOnlyInOne => "Group only in database 1",
+            DifferenceTypeKind::GroupOnlyInTwo => "Group only in database 2",
+            DifferenceTypeKind::EntryMoved => "Moved to another group",
+            DifferenceTypeKind::ExpiryDiffers => "Expiry differs",
+            DifferenceTypeKind::TagsDiffers => "Tags differ",
+            DifferenceTypeKind::AttachmentsDiffer => "Attachments differ",
+            DifferenceTypeKind::HistoryDiffers => "History differs",
+            DifferenceTypeKind::IconDiffers => "Icon differs",
+            DifferenceTypeKind::MetadataDiffers => "Usage metadata differs",
+            DifferenceTypeKind::ProtectionDiffers => "Field protection differs",
+            DifferenceTypeKind::PresenceDiffers => "Field presence differs",
+            DifferenceTypeKind::GroupMetadataDiffers => "Group settings differ",
+            DifferenceTypeKind::AutoTypeDiffers => "Auto-type differs",
+            DifferenceTypeKind::DanglingReference => "Dangling field reference",
+        }
+    }
+}
+
+pub fn count_by_kind(differences: &[Difference]) -> BTreeMap<DifferenceTypeKind, usize> {
+    let mut counts = BTreeMap::new();
+    for diff in differences {
+        *counts.entry(diff.diff_type.kind()).or_insert(0) += 1;
+    }
+    counts
+}
+
+impl DifferenceType {
+    pub fn kind(&self) -> DifferenceTypeKind {
+        match self {
+            DifferenceType::OnlyInOne => DifferenceTypeKind::OnlyInOne,
+            DifferenceType::OnlyInTwo => DifferenceTypeKind::OnlyInTwo,
+            DifferenceType::TitleDiffers { .. } => DifferenceTypeKind::TitleDiffers,
+            DifferenceType::UsernameDiffers { .. } => DifferenceTypeKind::UsernameDiffers,
+            DifferenceType::PasswordDiffers { .. } => DifferenceTypeKind::PasswordDiffers,
+            DifferenceType::UrlDiffers { .. } => DifferenceTypeKind::UrlDiffers,
+            DifferenceType::NotesDiffers { .. } => DifferenceTypeKind::NotesDiffers,
+            DifferenceType::CustomFieldDiffers { .. } => DifferenceTypeKind::CustomFieldDiffers,
+            DifferenceType::TotpDiffers { .. } => DifferenceTypeKind::TotpDiffers,
+            DifferenceType::GroupOnlyInOne => DifferenceTypeKind::GroupOnlyInOne,
+            DifferenceType::GroupOnlyInTwo => DifferenceTypeKind::GroupOnlyInTwo,
+            DifferenceType::EntryMoved { .. } => DifferenceTypeKind::EntryMoved,
+            DifferenceType::ExpiryDiffers { .. } => DifferenceTypeKind::ExpiryDiffers,
+            DifferenceType::TagsDiffers { .. } => DifferenceTypeKind::TagsDiffers,
+            DifferenceType::AttachmentsDiffer { .. } => DifferenceTypeKind::AttachmentsDiffer,
+            DifferenceType::HistoryDiffers { .. } => DifferenceTypeKind::HistoryDiffers,
+            DifferenceType::IconDiffers { .. } => DifferenceTypeKind::IconDiffers,
+            DifferenceType::MetadataDiffers { .. } => DifferenceTypeKind::MetadataDiffers,
+            DifferenceType::ProtectionDiffers { .. } => DifferenceTypeKind::ProtectionDiffers,
+            DifferenceType::PresenceDiffers { .. } => DifferenceTypeKind::PresenceDiffers,
+            DifferenceType::GroupMetadataDiffers { .. } => DifferenceTypeKind::GroupMetadataDiffers,
+            DifferenceType::AutoTypeDiffers { .. } => DifferenceTypeKind::AutoTypeDiffers,
+            DifferenceType::DanglingReference { .. } => DifferenceTypeKind::DanglingReference,
+        }
+    }
+
+    /// The field name and both sides' values as they may appear in exports, with secrets redacted.
+    pub fn export_values(&self) -> (&str, Cow<'_, str>, Cow<'_, str>) {
+        let (field, value1, value2): (&str, &str, &str) = match self {
+            DifferenceType::OnlyInOne
+            | DifferenceType::OnlyInTwo
+            | DifferenceType::GroupOnlyInOne
+            | DifferenceType::GroupOnlyInTwo => ("", "", ""),
+            DifferenceType::TitleDiffers { title1, title2 } => ("Title", title1, title2),
+            DifferenceType::UsernameDiffers { username1, username2 } => ("UserName", username1, username2),
+            DifferenceType::PasswordDiffers { .. } => ("Password", REDACTED, REDACTED),
+            DifferenceType::UrlDiffers { url1, url2 } => ("URL", url1, url2),
+            DifferenceType::NotesDiffers { notes1, notes2 } => ("Notes", notes1, notes2),
+            DifferenceType::CustomFieldDiffers { field, protected: true, .. } => (field, REDACTED, REDACTED),
+            DifferenceType::CustomFieldDiffers { field, value1, value2, .. } => (field, value1, value2),
+            DifferenceType::TotpDiffers { .. } => ("otp", REDACTED, REDACTED),
+            DifferenceType::EntryMoved { from_path, to_path } => {
+                return ("Group", from_path.join("/").into(), to_path.join("/").into());
+            }
+            DifferenceType::ExpiryDiffers { expiry1, expiry2 } => ("Expires", expiry1, expiry2),
+            // Tags only in the first database go in value1, tags only in the second in value2
+            DifferenceType::TagsDiffers { added, removed } => {
+                return ("Tags", removed.join(", ").into(), added.join(", ").into());
+            }
+            DifferenceType::AttachmentsDiffer { added, removed, changed } => {
+                let side = |only: &[String]| only.iter().chain(changed).cloned().collect::<Vec<_>>().join(", ");
+                return ("Attachments", side(removed).into(), side(added).into());
+            }
+            DifferenceType::HistoryDiffers { count1, count2, latest1, latest2 } => {
+                let (history1, history2) = (describe_history(*count1, *latest1), describe_history(*count2, *latest2));
+                return ("History", history1.into(), history2.into());
+            }
+            DifferenceType::IconDiffers { icon1, icon2 } => return ("Icon", icon1.describe().into(), icon2.describe().into()),
+            DifferenceType::MetadataDiffers { usage_count1, usage_count2, last_access1, last_access2 } => {
+                let (usage1, usage2) = (describe_usage(*usage_count1, *last_access1), describe_usage(*usage_count2, *last_access2));
+                return ("Usage", usage1.into(), usage2.into());
+            }
+            DifferenceType::ProtectionDiffers { field, protected1 } => {
+                let describe = |protected: bool| if protected { "protected" } else { "not protected" };
+                (field, describe(*protected1), describe(!*protected1))
+            }
+            DifferenceType::PresenceDiffers { field, present1 } => {
+                let describe = |present: bool| if present { "empty" } else { "absent" };
+                (field, describe(*present1), describe(!*present1))
+            }
+            DifferenceType::GroupMetadataDiffers { field, value1, value2 } => (field, value1, value2),
+            DifferenceType::AutoTypeDiffers { autotype1, autotype2 } => {
+                return ("AutoType", autotype1.describe().into(), autotype2.describe().into());
+            }
+            // The unresolved reference goes on the side where it dangles
+            DifferenceType::DanglingReference { field, reference, dangling_in_first: true } => (field, reference, ""),
+            DifferenceType::DanglingReference { field, reference, dangling_in_first: false } => (field, "", reference),
+        };
+        (field, value1.into(), value2.into())
+    }
+
+    /// Both sides' raw values for the clipboard, and whether they are secret.
+    pub fn clipboard_values(&self) -> Option<(&str, &str, bool)> {
+        match self {
+            DifferenceType::TitleDiffers { title1, title2 } => Some((title1, title2, false)),
+            DifferenceType::UsernameDiffers { username1, username2 } => Some((username1, username2, false)),
+            DifferenceType::PasswordDiffers { password1, password2, .. } => Some((password1, password2, true)),
+            DifferenceType::UrlDiffers { url1, url2 } => Some((url1, url2, false)),
+            DifferenceType::NotesDiffers { notes1, notes2 } => Some((notes1, notes2, false)),
+            DifferenceType::CustomFieldDiffers { value1, value2, protected, .. } => Some((value1, value2, *protected)),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes through `export_values` so raw secrets can never reach a report.
+impl Serialize for DifferenceType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (field, value1, value2) = self.export_values();
+        let mut state = serializer.serialize_struct("DifferenceType", 4)?;
+        state.serialize_field("type", self.kind().name())?;
+        if field.is_empty() {
+            state.skip_field("field")?;
+            state.skip_field("value1")?;
+            state.skip_field("value2")?;
+        } else {
+            state.serialize_field("field", field)?;
+            state.serialize_field("value1", &value1)?;
+            state.serialize_field("value2", &value2)?;
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_kinds_are_listed_in_declaration_order() {
+        // The derived ordering follows the declaration, so a kind listed out of place breaks it
+        assert!(DifferenceTypeKind::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,328 @@
+//! Reading entry fields the way comparisons see them: OTP settings, expiry, tags, attachments, icons
+//! and auto-type, each normalized so that equivalent spellings compare equal.
+
+use chrono::NaiveDateTime;
+use keepass::db::{Entry, Value};
+use keepass::Database;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+/// Fields with dedicated comparisons; everything else is treated as a custom field.
+pub const STANDARD_FIELDS: [&str; 5] = ["Title", "UserName", "Password", "URL", "Notes"];
+
+/// Fields holding OTP configuration, compared together through `OtpConfig`.
+pub const OTP_FIELDS: [&str; 7] = [
+    "otp",
+    "TimeOtp-Secret",
+    "TimeOtp-Secret-Base32",
+    "TimeOtp-Secret-Hex",
+    "TimeOtp-Period",
+    "TimeOtp-Length",
+    "TimeOtp-Algorithm",
+];
+
+#[derive(Clone, PartialEq)]
+pub struct OtpConfig {
+    pub secret: Zeroizing<String>,
+    pub encoding: OtpSecretEncoding,
+    pub period: String,
+    pub digits: String,
+    pub algorithm: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OtpSecretEncoding {
+    Base32,
+    Hex,
+    /// KeePass' plain `TimeOtp-Secret`, used as raw UTF-8 bytes
+    Utf8,
+}
+
+pub struct TotpCode {
+    pub code: String,
+    pub remaining_secs: u64,
+    pub period_secs: u64,
+}
+
+impl OtpConfig {
+    pub fn from_entry(entry: &Entry) -> Option<OtpConfig> {
+        if let Some(otp) = entry.get("otp") {
+            return Some(Self::from_otp_value(otp));
+        }
+
+        let (secret, encoding) = if let Some(secret) = entry.get("TimeOtp-Secret-Base32") {
+            (normalize_otp_secret(secret), OtpSecretEncoding::Base32)
+        } else if let Some(secret) = entry.get("TimeOtp-Secret") {
+            (secret.to_string(), OtpSecretEncoding::Utf8)
+        } else {
+            (normalize_otp_secret(entry.get("TimeOtp-Secret-Hex")?), OtpSecretEncoding::Hex)
+        };
+        Some(OtpConfig {
+            secret: Zeroizing::new(secret),
+            encoding,
+            period: entry.get("TimeOtp-Period").unwrap_or("30").trim().to_string(),
+            digits: entry.get("TimeOtp-Length").unwrap_or("6").trim().to_string(),
+            algorithm: normalize_otp_algorithm(entry.get("TimeOtp-Algorithm").unwrap_or("SHA1")),
+        })
+    }
+
+    /// Parses either an `otpauth://` URL or a bare secret.
+    pub fn from_otp_value(value: &str) -> OtpConfig {
+        let mut config = OtpConfig {
+            secret: Zeroizing::default(),
+            encoding: OtpSecretEncoding::Base32,
+            period: String::from("30"),
+            digits: String::from("6"),
+            algorithm: String::from("SHA1"),
+        };
+
+        let value = value.trim();
+        if !value.starts_with("otpauth://") {
+            config.secret = Zeroizing::new(normalize_otp_secret(value));
+            return config;
+        }
+
+        let query = value.split_once('?').map(|(_, q)| q).unwrap_or_default();
+        for pair in query.split('&') {
+            let (name, param) = pair.split_once('=').unwrap_or((pair, ""));
+            match name.to_ascii_lowercase().as_str() {
+                "secret" => config.secret = Zeroizing::new(normalize_otp_secret(param)),
+                "period" => config.period = param.trim().to_string(),
+                "digits" => config.digits = param.trim().to_string(),
+                "algorithm" => config.algorithm = normalize_otp_algorithm(param),
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// The code valid at `unix_time`, or `None` if the configuration cannot produce one.
+    pub fn code_at(&self, unix_time: u64) -> Option<TotpCode> {
+        let secret = Zeroizing::new(match self.encoding {
+            OtpSecretEncoding::Base32 => base32::decode(base32::Alphabet::Rfc4648 { padding: false }, &self.secret)?,
+            OtpSecretEncoding::Hex => decode_hex(&self.secret)?,
+            OtpSecretEncoding::Utf8 => self.secret.as_bytes().to_vec(),
+        });
+        let period: u64 = self.period.parse().ok().filter(|period| *period > 0)?;
+        let digits: u32 = self.digits.parse().ok()?;
+        let code = match self.algorithm.as_str() {
+            "SHA1" => totp_lite::totp_custom::<totp_lite::Sha1>(period, digits, &secret, unix_time),
+            "SHA256" => totp_lite::totp_custom::<totp_lite::Sha256>(period, digits, &secret, unix_time),
+            "SHA512" => totp_lite::totp_custom::<totp_lite::Sha512>(period, digits, &secret, unix_time),
+            _ => return None,
+        };
+        Some(TotpCode {
+            code,
+            remaining_secs: period - unix_time % period,
+            period_secs: period,
+        })
+    }
+}
+
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn normalize_otp_secret(secret: &str) -> String {
+    secret
+        .replace("%20", "")
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+fn normalize_otp_algorithm(algorithm: &str) -> String {
+    algorithm.trim().replace('-', "").to_ascii_uppercase()
+}
+
+/// When the entry expires, or `None` if expiry is switched off. KeePass stores these times in UTC.
+pub fn expiry_time(entry: &Entry) -> Option<NaiveDateTime> {
+    if entry.times.expires {
+        entry.times.get_expiry().copied()
+    } else {
+        None
+    }
+}
+
+pub fn is_expired(entry: &Entry, now: NaiveDateTime) -> bool {
+    expiry_time(entry).is_some_and(|expiry| expiry <= now)
+}
+
+/// An entry's tags with order, surrounding whitespace and separator style ignored.
+pub fn tag_set(entry: &Entry) -> BTreeSet<String> {
+    entry
+        .tags
+        .iter()
+        .flat_map(|tags| tags.split([',', ';']))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// SHA-256 of each binary field by name, so attachments are compared without holding on to their content.
+/// The keepass crate does not yet link attachments parsed from KDBX files to their entries, so only
+/// binaries it exposes as `Value::Bytes` fields are seen here; the rest are only counted, per database.
+pub fn attachment_hashes(entry: &Entry) -> BTreeMap<&str, String> {
+    entry
+        .fields
+        .iter()
+        .filter_map(|(name, value)| match value {
+            Value::Bytes(bytes) => {
+                let hash = Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect();
+                Some((name.as_str(), hash))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// How many prior versions an entry keeps, and when the newest of them was last modified.
+pub fn history_summary(entry: &Entry) -> (usize, Option<NaiveDateTime>) {
+    let versions = entry.history.as_ref().map(|history| history.get_entries().as_slice()).unwrap_or_default();
+    let latest = versions.iter().filter_map(|version| version.times.get_last_modification()).max().copied();
+    (versions.len(), latest)
+}
+
+pub fn describe_history(count: usize, latest: Option<NaiveDateTime>) -> String {
+    match latest {
+        Some(latest) => format!("{} versions, latest {}", count, latest),
+        None => format!("{} versions", count),
+    }
+}
+
+/// An entry's built-in icon number and, when set, the custom icon shown in its place.
+#[derive(Clone, PartialEq)]
+pub struct EntryIcon {
+    pub id: Option<usize>,
+    /// SHA-256 of the custom icon image, or the UUID of a custom icon missing from the database
+    pub custom: Option<Result<String, Uuid>>,
+}
+
+impl EntryIcon {
+    pub fn of(entry: &Entry, custom_icons: &HashMap<Uuid, String>) -> Self {
+        Self {
+            id: entry.icon_id,
+            custom: entry
+                .custom_icon_uuid
+                .map(|uuid| custom_icons.get(&uuid).cloned().ok_or(uuid)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let standard = self.id.map_or_else(|| String::from("default icon"), |id| format!("icon #{}", id));
+        match &self.custom {
+            Some(Ok(hash)) => format!("custom image {} (over {})", &hash[..12], standard),
+            Some(Err(uuid)) => format!("missing custom icon {} (over {})", uuid, standard),
+            None => standard,
+        }
+    }
+}
+
+/// An entry's auto-type settings, normalized so that spacing and placeholder case don't count as changes.
+#[derive(Clone, PartialEq)]
+pub struct AutoTypeSettings {
+    pub enabled: bool,
+    /// Empty when the entry types its group's default sequence
+    pub sequence: String,
+    /// Window title patterns and the sequence typed into matching windows
+    pub associations: BTreeMap<String, String>,
+}
+
+impl AutoTypeSettings {
+    pub fn of(entry: &Entry) -> Self {
+        let Some(autotype) = &entry.autotype else {
+            // KeePass treats an entry without auto-type settings as enabled with the group default
+            return Self { enabled: true, sequence: String::new(), associations: BTreeMap::new() };
+        };
+        Self {
+            enabled: autotype.enabled,
+            sequence: normalize_sequence(autotype.sequence.as_deref().unwrap_or_default()),
+            associations: autotype
+                .associations
+                .iter()
+                .map(|association| {
+                    (
+                        association.window.as_deref().unwrap_or_default().trim().to_string(),
+                        normalize_sequence(association.sequence.as_deref().unwrap_or_default()),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn describe_sequence(sequence: &str) -> &str {
+        if sequence.is_empty() {
+            "(group default)"
+        } else {
+            sequence
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = if self.enabled {
+            Self::describe_sequence(&self.sequence).to_string()
+        } else {
+            String::from("disabled")
+        };
+        for (window, sequence) in &self.associations {
+            description.push_str(&format!("; {} → {}", window, Self::describe_sequence(sequence)));
+        }
+        description
+    }
+}
+
+/// Trims an auto-type sequence and upper-cases its placeholder names, which KeePass matches in any
+/// case. Arguments such as the field name in `{S:Field}` are kept as they are.
+pub fn normalize_sequence(sequence: &str) -> String {
+    let mut normalized = String::new();
+    let mut in_name = false;
+    for c in sequence.trim().chars() {
+        match c {
+            '{' => in_name = true,
+            '}' | ':' | ' ' | '=' => in_name = false,
+            _ => {}
+        }
+        if in_name {
+            normalized.extend(c.to_uppercase());
+        } else {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+/// SHA-256 of each custom icon image by UUID, so the same image stored under two UUIDs compares equal.
+pub fn custom_icon_hashes(db: &Database) -> HashMap<Uuid, String> {
+    db.meta
+        .custom_icons
+        .icons
+        .iter()
+        .map(|icon| (icon.uuid, Sha256::digest(&icon.data).iter().map(|b| format!("{:02x}", b)).collect()))
+        .collect()
+}
+
+pub fn describe_usage(count: usize, last_access: Option<NaiveDateTime>) -> String {
+    match last_access {
+        Some(last_access) => format!("used {} times, last accessed {}", count, last_access),
+        None => format!("used {} times", count),
+    }
+}
+
+pub fn describe_expiry(expiry: Option<NaiveDateTime>) -> String {
+    expiry.map(|t| t.to_string()).unwrap_or_else(|| String::from("Never"))
+}
+
+/// Estimates how hard a password is to guess, from 0 (very weak) to 4 (very strong).
+pub fn password_strength(password: &str, hints: &[&str]) -> u8 {
+    zxcvbn::zxcvbn(password, hints).score().into()
+}
@@ -0,0 +1,83 @@
+//! The comparison engine behind RustPass, for embedding in other tools.
+//!
+//! Open both databases with the `keepass` crate, then call [`compare()`] with the [`CompareOptions`]
+//! to use. Every changed field, entry only in one database, moved entry and group difference comes
+//! back as a [`Difference`], which the [`report`] module can turn into JSON, CSV, Markdown or HTML.
+//! Opening files, keychains and the user interface stay in the `rustpass` binary.
+
+use keepass::Database;
+use std::sync::atomic::AtomicBool;
+
+pub mod compare;
+pub mod difference;
+pub mod fields;
+pub mod report;
+#[cfg(test)]
+mod test_support;
+
+pub use compare::{CompareOptions, MatchStrategy};
+pub use difference::{Difference, DifferenceType, DifferenceTypeKind};
+
+/// Every difference between `db1` and `db2`, sorted by title.
+pub fn compare(db1: &Database, db2: &Database, opts: CompareOptions) -> Vec<Difference> {
+    opts.compare_databases(db1, db2, &AtomicBool::new(false), &|_, _| {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry_mut, DatabaseBuilder, EntryBuilder, GroupBuilder, TempDatabase};
+    use keepass::db::Value;
+
+    fn sample() -> Database {
+        DatabaseBuilder::new()
+            .entry(EntryBuilder::new("Mail").username("alice").password("hunter2").url("https://mail.example"))
+            .group(
+                GroupBuilder::new("Work")
+                    .entry(EntryBuilder::new("VPN").username("alice").password("s3cret").field("Server", "vpn1"))
+                    .group(GroupBuilder::new("Servers").entry(EntryBuilder::new("Router").password("admin"))),
+            )
+            .build()
+    }
+
+    #[test]
+    fn identical_databases_have_no_differences() {
+        let db1 = sample();
+        let db2 = db1.clone();
+        assert!(compare(&db1, &db2, CompareOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn changed_password_is_one_difference() {
+        let db1 = sample();
+        let mut db2 = db1.clone();
+        entry_mut(&mut db2, "VPN").fields.insert(String::from("Password"), Value::Protected("changed".as_bytes().into()));
+
+        let differences = compare(&db1, &db2, CompareOptions::default());
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].diff_type.kind(), DifferenceTypeKind::PasswordDiffers);
+        assert_eq!(differences[0].title, "VPN");
+        assert_eq!(differences[0].group_path, "Work");
+    }
+
+    #[test]
+    fn added_entry_is_only_in_two() {
+        let db1 = sample();
+        let mut db2 = db1.clone();
+        db2.root.add_child(EntryBuilder::new("Bank").username("alice").build());
+
+        let differences = compare(&db1, &db2, CompareOptions::default());
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].diff_type.kind(), DifferenceTypeKind::OnlyInTwo);
+        assert_eq!(differences[0].title, "Bank");
+    }
+
+    #[test]
+    fn saved_and_reopened_database_is_unchanged() {
+        let db = sample();
+        let file = TempDatabase::write(&db, "pw");
+        assert!(file.path().exists());
+        let reopened = file.open("pw");
+        assert!(compare(&db, &reopened, CompareOptions::default()).is_empty());
+    }
+}
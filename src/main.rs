@@ -1,219 +1,2668 @@
+use base64::Engine;
+use chrono::{DateTime, Local, NaiveDateTime};
 use eframe::egui;
-use keepass::{Database, DatabaseKey};
-use keepass::db::{Entry, Group};
-use std::collections::HashMap;
-use std::fs::File;
+use keepass::Database;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+mod profile;
+mod sync;
+
+use rustpass::compare::{
+    display_title, DriftMatrix, DriftState, ExplorerGroup, IdenticalEntry, MergeAction, PlannedAction, Resolution,
+};
+use rustpass::difference::{count_by_kind, EntryId, Freshness};
+use rustpass::fields::{describe_history, describe_usage, AutoTypeSettings};
+use rustpass::report::{anonymize, differences_to_csv, differences_to_html, differences_to_json, differences_to_markdown};
+use rustpass::{CompareOptions, Difference, DifferenceType, DifferenceTypeKind, MatchStrategy};
+use profile::SettingsProfile;
+use sync::{is_remote_path, SnapshotStatus, SyncJob, SyncOutcome, SyncProgress, UnlockedDatabase, CANCELLED_MESSAGE, LOCKED_FILE_MESSAGE};
 
 fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let in_memory = args == ["--no-persist"];
+    if !args.is_empty() && !in_memory {
+        std::process::exit(run_cli(&args));
+    }
+
+    // The size here only applies on first launch; afterwards eframe restores the last window geometry,
+    // clamped so a disconnected monitor can't leave the window off-screen
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([800.0, 600.0])
+            .with_min_inner_size([480.0, 360.0])
+            .with_clamp_size_to_monitor_size(true)
             .with_title("RustPass - KeePass Database Sync"),
+        persist_window: !in_memory,
+        // Pointing eframe at the settings file instead of letting it find one keeps it from creating the
+        // data directory; the file is still read, but never written because nothing marks the storage dirty
+        persistence_path: in_memory
+            .then(|| eframe::storage_dir("RustPass").map(|dir| dir.join("app.ron")).unwrap_or_default()),
         ..Default::default()
     };
 
     eframe::run_native(
         "RustPass",
         options,
-        Box::new(|_cc| Ok(Box::new(RustPassApp::default()))),
+        Box::new(|cc| Ok(Box::new(RustPassApp::new(cc, in_memory)))),
     )
 }
 
+const CLI_USAGE: &str = "Usage: rustpass compare [--quiet] [--format text|json] [--anonymize] <db1.kdbx> <db2.kdbx>
+
+Options:
+  -q, --quiet       Print nothing; only the exit code reports the result
+  --format FORMAT   Print a text summary (default) or the differences as JSON
+  --anonymize       Replace titles, usernames and group names with placeholders
+  -h, --help        Show this help
+
+Exit codes:
+  0  The databases are identical
+  1  Differences were found
+  2  Error, such as a wrong password, a missing file or invalid arguments
+
+Passwords are read from RUSTPASS_PASS1 and RUSTPASS_PASS2, or prompted for interactively.
+Run without arguments to start the GUI, or with --no-persist to start it without writing anything to disk.";
+
+#[derive(Clone, Copy)]
+enum CliFormat {
+    Text,
+    Json,
+}
+
+struct CliOptions {
+    quiet: bool,
+    format: CliFormat,
+    anonymize: bool,
+    path1: String,
+    path2: String,
+}
+
+/// Parses the arguments after the program name; `Ok(None)` means help was requested.
+fn parse_cli_args(args: &[String]) -> Result<Option<CliOptions>, String> {
+    let mut quiet = false;
+    let mut format = CliFormat::Text;
+    let mut anonymize = false;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-q" | "--quiet" => quiet = true,
+            "--anonymize" => anonymize = true,
+            "--format" => {
+                format = match args.next().map(String::as_str) {
+                    Some("text") => CliFormat::Text,
+                    Some("json") => CliFormat::Json,
+                    Some(other) => return Err(format!("Unknown format \"{}\"; expected text or json", other)),
+                    None => return Err(String::from("--format needs a value")),
+                }
+            }
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    match <[String; 3]>::try_from(positional) {
+        Ok([command, path1, path2]) if command == "compare" => Ok(Some(CliOptions { quiet, format, anonymize, path1, path2 })),
+        _ => Err(String::from("Expected: compare <db1.kdbx> <db2.kdbx>")),
+    }
+}
+
+/// Runs a headless comparison and returns the process exit code.
+fn run_cli(args: &[String]) -> i32 {
+    let options = match parse_cli_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", CLI_USAGE);
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, CLI_USAGE);
+            return 2;
+        }
+    };
+    let (path1, path2) = (&options.path1, &options.path2);
+    if is_same_file(path1, path2) {
+        eprintln!("{}", SAME_FILE_WARNING);
+        return 2;
+    }
+
+    let (pass1, pass2) = match cli_passwords(path1, path2) {
+        Ok(passwords) => passwords,
+        Err(e) => {
+            eprintln!("Failed to read password: {}", e);
+            return 2;
+        }
+    };
+
+    let mut job = SyncJob {
+        database1_path: path1.clone(),
+        database1_pass: Zeroizing::new(pass1),
+        database1_keyfile: String::new(),
+        database1_key_element: Zeroizing::default(),
+        database1_yubikey_slot: None,
+        database2_path: path2.clone(),
+        database2_pass: Zeroizing::new(pass2),
+        database2_keyfile: String::new(),
+        database2_key_element: Zeroizing::default(),
+        database2_yubikey_slot: None,
+        extra_sources: Vec::new(),
+        options: CompareOptions::default(),
+        open_attempts: 1,
+        snapshot_mode: false,
+        unlocked: Default::default(),
+        cancel: Default::default(),
+    };
+
+    let outcome = match job.run(&|_| {}) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    // Only the differences are reported, so release the decrypted databases right away
+    drop(outcome.databases);
+
+    if !options.quiet {
+        // On stderr, so JSON output stays parseable
+        for (index, weak_kdf) in outcome.weak_kdfs.iter().enumerate() {
+            if let Some(weak_kdf) = weak_kdf {
+                eprintln!("Warning: database {} uses {}, below the recommended strength", index + 1, weak_kdf);
+            }
+        }
+        for (index, count) in outcome.uncompared_attachments.iter().enumerate() {
+            if *count > 0 {
+                eprintln!("Note: database {} has {} attachments, which are not compared", index + 1, count);
+            }
+        }
+        let differences = if options.anonymize {
+            Cow::Owned(anonymize(&outcome.differences))
+        } else {
+            Cow::Borrowed(&outcome.differences[..])
+        };
+        match options.format {
+            CliFormat::Json => match differences_to_json(&differences) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    eprintln!("Error exporting JSON: {}", e);
+                    return 2;
+                }
+            },
+            CliFormat::Text => {
+                println!("Database 1: {} entries", outcome.entry_count1);
+                println!("Database 2: {} entries", outcome.entry_count2);
+                println!("Differences found: {}", outcome.differences.len());
+                println!("Identical entries: {}", outcome.identical.len());
+                for diff in differences.iter() {
+                    let location = if diff.group_path.is_empty() {
+                        diff.title.clone()
+                    } else {
+                        format!("{}/{}", diff.group_path, diff.title)
+                    };
+                    println!("  {}: {}", location, diff.diff_type.kind().label());
+                }
+            }
+        }
+    }
+
+    if outcome.differences.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn cli_passwords(path1: &str, path2: &str) -> std::io::Result<(String, String)> {
+    Ok((cli_password("RUSTPASS_PASS1", path1)?, cli_password("RUSTPASS_PASS2", path2)?))
+}
+
+/// Passwords never come from argv, where other users could read them from the process list.
+fn cli_password(variable: &str, path: &str) -> std::io::Result<String> {
+    match std::env::var(variable) {
+        Ok(password) => Ok(password),
+        Err(_) => rpassword::prompt_password(format!("Password for {}: ", path)),
+    }
+}
+
 struct RustPassApp {
     database1_path: String,
-    database1_pass: String,
+    database1_pass: Zeroizing<String>,
+    database1_keyfile: String,
+    database1_key_element: Zeroizing<String>,
+    database1_credential: CredentialType,
+    database1_yubikey_slot: Option<u8>,
+    show_pass1: bool,
+    /// Save the password in the OS keychain once the database opens successfully
+    remember_pass1: bool,
     database2_path: String,
-    database2_pass: String,
+    database2_pass: Zeroizing<String>,
+    database2_keyfile: String,
+    database2_key_element: Zeroizing<String>,
+    database2_credential: CredentialType,
+    database2_yubikey_slot: Option<u8>,
+    show_pass2: bool,
+    remember_pass2: bool,
+    /// Unlock the second database with the first one's password, hiding its own input
+    same_password: bool,
+    /// Further databases shown only in the drift overview against the first database
+    extra_sources: Vec<DatabaseSource>,
+    /// Compare the first database against its snapshot from the last run, ignoring all others
+    snapshot_mode: bool,
+    /// Group path to restrict comparing to, picked from the first database's groups
+    subtree: Vec<String>,
+    focused_target: Option<DatabaseTarget>,
+    recent_paths: Vec<String>,
+    theme: Theme,
+    palette: DifferencePalette,
+    /// The latest log message or sync progress, shown below the controls
     status_message: String,
-    differences: Vec<DifferenceInfo>,
+    /// What happened this session, oldest first and capped at `MAX_LOG_ENTRIES`
+    log_entries: Vec<LogEntry>,
+    match_strategy: MatchStrategy,
+    /// Field that identifies entries under `MatchStrategy::ByField`
+    match_field: String,
+    /// Field names found by the last comparison, offered as match fields
+    field_names: Vec<String>,
+    include_recycle_bin: bool,
+    compare_groups: bool,
+    compare_history: bool,
+    /// Last access time and usage count, which change on every use and so are off by default
+    compare_usage_metadata: bool,
+    ignore_trailing_whitespace: bool,
+    strict_presence: bool,
+    /// Comma-separated custom field names left out of the comparison
+    ignored_fields: String,
+    /// Comma-separated group names or paths, with wildcards, skipped along with their subgroups
+    excluded_groups: String,
+    differences: Vec<Difference>,
+    /// Set once a comparison finished successfully, so an empty result reads as "in sync"
+    has_compared: bool,
+    /// The results compare snapshot hashes rather than values, so there is nothing to reveal, copy or rate
+    hashed_results: bool,
+    drift: Option<DriftMatrix>,
+    trees: Option<[ExplorerGroup; 2]>,
+    /// Entries found in both databases without any difference
+    identical: Vec<IdenticalEntry>,
+    /// Show the identical entries in a muted style after the differences
+    show_identical: bool,
+    /// Replace titles, usernames and group names with placeholders in exported reports
+    anonymize_exports: bool,
+    /// Show both group hierarchies side by side above the differences
+    show_tree: bool,
+    /// Append how long decrypting and comparing took to the status after each sync
+    show_timings: bool,
+    /// Key of the differences to scroll into view, as picked in the tree explorer
+    scroll_to_key: Option<String>,
+    filter_text: String,
+    sort_order: SortOrder,
+    conflicts_only: bool,
+    /// The master database, when the other one is read as a copy that may have drifted from it
+    authoritative: Option<DatabaseTarget>,
+    visible_kinds: HashSet<DifferenceTypeKind>,
+    revealed_fields: HashSet<usize>,
+    reveal_deadline: Option<Instant>,
+    clipboard_clears: Vec<(Instant, String)>,
+    sync_receiver: Option<Receiver<SyncMessage>>,
+    /// Path, file format and any weak key derivation settings of each database as last opened,
+    /// shown while the path is unchanged
+    loaded_formats: [Option<(String, String, Option<String>)>; 2],
+    /// Shared with the running sync's worker, which stops at its next checkpoint once it is set
+    sync_cancel: Arc<AtomicBool>,
+    /// Paths and passwords to save in the keychain if the running sync succeeds
+    pending_saved_passwords: Vec<(String, Zeroizing<String>)>,
+    sync_progress: Option<SyncProgress>,
+    last_synced: Option<DateTime<Local>>,
+    /// File names of the first and second database as of the last sync, for labelling results
+    source_names: [String; 2],
+    watcher: Option<FileWatcher>,
+    /// When a watched file last changed, while waiting out `WATCH_DEBOUNCE`
+    changed_at: Option<Instant>,
+    /// The compared databases and the options behind `differences`, so a merge never picks up settings
+    /// changed since
+    databases: Option<(Database, Database, CompareOptions)>,
+    /// Databases that opened during a failed sync, skipped when decrypting again
+    unlocked: [Option<UnlockedDatabase>; 2],
+    resolution: HashMap<EntryId, Resolution>,
+    resolution_undo: Vec<ResolutionChange>,
+    resolution_redo: Vec<ResolutionChange>,
+    /// The last sync failed on a locked file, so offer to try again once it is closed
+    locked_retry: bool,
+    /// Save target that already exists, waiting for the user to confirm overwriting it
+    pending_overwrite: Option<PathBuf>,
+    backup_on_overwrite: bool,
+    show_about: bool,
+    show_shortcuts: bool,
+    show_palette: bool,
+    /// What saving would do, shown in place of the differences until the user goes back
+    merge_preview: Option<Vec<PlannedAction>>,
+    /// Settings last used for each pair of databases, keyed by `pair_profile_key`
+    pair_profiles: HashMap<String, PairProfile>,
+    /// Save recent paths and settings encrypted with a key kept in the OS keychain
+    encrypt_settings: bool,
+    /// The encrypted settings could not be read at startup, so they must not be overwritten
+    settings_unreadable: bool,
+    /// Started with `--no-persist`: nothing is written to disk or the keychain
+    in_memory: bool,
+    /// Paths for which the pair profile was last looked up, so it is restored only when they change
+    profile_paths: (String, String),
+    /// `pair_profile_key` of the databases behind the current results
+    compared_pair: Option<String>,
+}
+
+/// Order in which the differences list is displayed.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum SortOrder {
+    #[default]
+    Title,
+    GroupPath,
+    DifferenceType,
+}
+
+impl SortOrder {
+    const ALL: [SortOrder; 3] = [
+        SortOrder::Title,
+        SortOrder::GroupPath,
+        SortOrder::DifferenceType,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            SortOrder::Title => "Title A-Z",
+            SortOrder::GroupPath => "Group path",
+            SortOrder::DifferenceType => "Difference type",
+        }
+    }
+}
+
+const NOTES_PREVIEW_CHARS: usize = 200;
+
+fn truncate_preview(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn strength_label(strength: u8) -> (&'static str, egui::Color32) {
+    match strength {
+        0 => ("very weak", egui::Color32::RED),
+        1 => ("weak", egui::Color32::LIGHT_RED),
+        2 => ("fair", egui::Color32::YELLOW),
+        3 => ("strong", egui::Color32::LIGHT_GREEN),
+        _ => ("very strong", egui::Color32::GREEN),
+    }
+}
+
+/// Values sharing less than this fraction of their characters are shown whole instead of highlighted.
+const MIN_HIGHLIGHT_SIMILARITY: f32 = 0.5;
+
+/// Shows both values on a line each, with the characters only one side has on a colored background.
+/// Hashes are never highlighted, as the characters they share mean nothing.
+fn highlighted_values(ui: &mut egui::Ui, value1: &str, value2: &str, hashed: bool) {
+    let diff = similar::TextDiff::from_chars(value1, value2);
+    if hashed || diff.ratio() < MIN_HIGHLIGHT_SIMILARITY {
+        ui.label(format!("  DB1: {}", value1));
+        ui.label(format!("  DB2: {}", value2));
+        return;
+    }
+
+    let font_id = egui::TextStyle::Body.resolve(ui.style());
+    let text_color = ui.visuals().text_color();
+    let plain = egui::TextFormat::simple(font_id, text_color);
+    let highlighted = |background| egui::TextFormat {
+        background,
+        ..plain.clone()
+    };
+    let removed = highlighted(egui::Color32::from_rgba_unmultiplied(255, 80, 80, 90));
+    let added = highlighted(egui::Color32::from_rgba_unmultiplied(80, 200, 80, 90));
+
+    let mut jobs = [egui::text::LayoutJob::default(), egui::text::LayoutJob::default()];
+    jobs[0].append("  DB1: ", 0.0, plain.clone());
+    jobs[1].append("  DB2: ", 0.0, plain.clone());
+    for change in diff.iter_all_changes() {
+        let (sides, format): (&[usize], _) = match change.tag() {
+            similar::ChangeTag::Equal => (&[0, 1], &plain),
+            similar::ChangeTag::Delete => (&[0], &removed),
+            similar::ChangeTag::Insert => (&[1], &added),
+        };
+        for &side in sides {
+            jobs[side].append(change.value(), 0.0, format.clone());
+        }
+    }
+    for job in jobs {
+        ui.label(job);
+    }
+}
+
+/// Counts the lines only present in the first and only present in the second text.
+fn count_changed_lines(text1: &str, text2: &str) -> (usize, usize) {
+    let lines1: Vec<&str> = text1.lines().collect();
+    let lines2: Vec<&str> = text2.lines().collect();
+    let removed = lines1.iter().filter(|l| !lines2.contains(l)).count();
+    let added = lines2.iter().filter(|l| !lines1.contains(l)).count();
+    (removed, added)
+}
+
+const SHORTCUT_BROWSE_FIRST: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Num1);
+const SHORTCUT_BROWSE_SECOND: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Num2);
+const SHORTCUT_SYNC: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Enter);
+const SHORTCUT_CLEAR: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::L);
+const SHORTCUT_UNDO: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+const SHORTCUT_REDO: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+
+/// Every keyboard shortcut with what it does, as listed under Help.
+const SHORTCUTS: [(&str, egui::KeyboardShortcut); 6] = [
+    ("Browse for the first database", SHORTCUT_BROWSE_FIRST),
+    ("Browse for the second database", SHORTCUT_BROWSE_SECOND),
+    ("Compare the databases", SHORTCUT_SYNC),
+    ("Clear all paths, passwords and results", SHORTCUT_CLEAR),
+    ("Undo a resolution choice", SHORTCUT_UNDO),
+    ("Redo a resolution choice", SHORTCUT_REDO),
+];
+
+/// Most resolution changes that can be undone.
+const MAX_UNDO_STEPS: usize = 100;
+
+/// How long revealed secrets stay visible without any clicks, keys or scrolling.
+const REVEAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a copied secret stays on the clipboard before it is cleared.
+const CLIPBOARD_CLEAR_DELAY: Duration = Duration::from_secs(12);
+
+/// Quiet period after the last file change before a watched pair is compared again.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Tries at opening each database while watching, in case KeePass is still writing it.
+const WATCH_OPEN_ATTEMPTS: u32 = 3;
+
+const WATCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Notifies about changes to the two database files by watching their directories,
+/// since editors usually save by replacing the file.
+struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    files: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    fn new(ctx: &egui::Context, paths: [&str; 2]) -> Result<Self, String> {
+        let files: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| std::fs::canonicalize(path).map_err(|e| format!("Cannot watch {}: {}", path, e)))
+            .collect::<Result<_, _>>()?;
+
+        let (sender, events) = mpsc::channel();
+        let ctx = ctx.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+            ctx.request_repaint();
+        })
+        .map_err(|e| format!("Failed to start watching: {}", e))?;
+        for directory in files.iter().filter_map(|file| file.parent()) {
+            watcher
+                .watch(directory, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("Cannot watch {}: {}", directory.display(), e))?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            files,
+        })
+    }
+
+    /// Drains pending events, reporting whether either database file was written, created or replaced.
+    fn changed(&self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            // Our own reads show up as access events; ignore them so comparing doesn't retrigger itself
+            let Ok(event) = event else { continue };
+            if !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|path| self.files.contains(path)) {
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// File extensions offered when picking or dropping databases. KeePass 1.x `.kdb` files are read-only.
+const DATABASE_EXTENSIONS: [&str; 2] = ["kdbx", "kdb"];
+
+/// Service name under which passwords are stored in the OS keychain, one entry per database path.
+const KEYRING_SERVICE: &str = "rustpass";
+
+fn keyring_entry(path: &str) -> keyring::Result<keyring::Entry> {
+    // The same file reached through different spellings of its path should share one entry
+    let path = std::fs::canonicalize(path).map_or_else(|_| path.to_string(), |p| p.display().to_string());
+    keyring::Entry::new(KEYRING_SERVICE, &path)
+}
+
+fn saved_password(path: &str) -> Option<Zeroizing<String>> {
+    if path.is_empty() {
+        return None;
+    }
+    keyring_entry(path).and_then(|entry| entry.get_password()).ok().map(Zeroizing::new)
+}
+
+fn save_password(path: &str, password: &str) -> Result<(), String> {
+    keyring_entry(path)
+        .and_then(|entry| entry.set_password(password))
+        .map_err(|e| e.to_string())
+}
+
+fn forget_password(path: &str) -> Result<(), String> {
+    match keyring_entry(path).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Splits a comma-separated list of field names, dropping blanks around and between them.
+fn parse_field_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether two paths lead to the same file, following symlinks and relative paths.
+fn is_same_file(path1: &str, path2: &str) -> bool {
+    match (std::fs::canonicalize(path1), std::fs::canonicalize(path2)) {
+        (Ok(canonical1), Ok(canonical2)) => canonical1 == canonical2,
+        _ => is_remote_path(path1) && path1 == path2,
+    }
+}
+
+/// Shown instead of comparing a database with itself, which would misleadingly report no differences.
+const SAME_FILE_WARNING: &str = "⚠ Both slots point to the same file; pick two different databases to compare";
+
+/// Hover text for controls that would write to disk or the keychain, which `--no-persist` turns off.
+const IN_MEMORY_DISABLED: &str = "Not available in in-memory mode, which writes nothing to disk or the keychain";
+
+/// The file name of a database path, which is what users recognize their vaults by.
+fn file_display_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or_else(|| path.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+fn has_database_extension(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| DATABASE_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+}
+
+/// Why a database path can't be synced yet, checked before any decryption is attempted.
+/// Empty paths are left to the Sync button's own check.
+fn path_problem(path: &str) -> Option<&'static str> {
+    // Whether a URL leads anywhere is only known once it is downloaded
+    if is_remote_path(path) {
+        return None;
+    }
+    let path = Path::new(path);
+    if path.as_os_str().is_empty() {
+        None
+    } else if !path.exists() {
+        Some("File not found")
+    } else if !path.is_file() {
+        Some("Not a file")
+    } else if !has_database_extension(path) {
+        Some("Not a .kdbx or .kdb file")
+    } else {
+        None
+    }
+}
+
+fn path_hint(ui: &mut egui::Ui, path: &str) {
+    if let Some(problem) = path_problem(path) {
+        ui.colored_label(egui::Color32::RED, problem);
+    }
+}
+
+/// Notes the file format of the database last opened from `path`, once it has been compared, and
+/// warns when its key derivation settings are weak.
+fn loaded_format_hint(ui: &mut egui::Ui, loaded: &Option<(String, String, Option<String>)>, path: &str) {
+    if let Some((_, format, weak_kdf)) = loaded.as_ref().filter(|(loaded_path, _, _)| loaded_path == path) {
+        ui.weak(format!("loaded — {}", format));
+        if let Some(weak_kdf) = weak_kdf {
+            ui.colored_label(egui::Color32::from_rgb(255, 200, 0), "⚠ weak key derivation").on_hover_text(format!(
+                "This database uses {}, below the recommended strength against guessing the master key. \
+                 Raise the key derivation settings in your KeePass client.",
+                weak_kdf
+            ));
+        }
+    }
+}
+
+/// Hints at a master password that is missing or very short, before anything is decrypted.
+fn password_hint(ui: &mut egui::Ui, password: &str, expected: bool) {
+    if password.is_empty() {
+        if expected {
+            ui.weak("no password entered");
+        }
+    } else if password.chars().count() < MIN_PASSWORD_LENGTH {
+        ui.weak("very short password");
+    }
+}
+
+const MAX_RECENT_PATHS: usize = 5;
+/// Master passwords shorter than this get a hint next to the password field.
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// State kept between sessions via eframe's storage. Never holds passwords.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PersistedState {
+    database1_path: String,
+    database2_path: String,
+    recent_paths: Vec<String>,
+    theme: Theme,
+    palette: DifferencePalette,
+    show_tree: bool,
+    show_timings: bool,
+    show_identical: bool,
+    anonymize_exports: bool,
+    ignored_fields: String,
+    pair_profiles: HashMap<String, PairProfile>,
+    /// The real state is in `ENCRYPTED_STATE_KEY`; this copy holds nothing else
+    encrypt_settings: bool,
+}
+
+/// Storage key of the encrypted `PersistedState`, written instead of the plain one when enabled.
+const ENCRYPTED_STATE_KEY: &str = "encrypted_state";
+
+/// Keychain account holding the key the saved settings are encrypted with.
+const SETTINGS_KEY_ACCOUNT: &str = "settings encryption key";
+
+/// The settings key from the keychain, generating and storing one first if `create` is set.
+fn settings_key(create: bool) -> Result<chacha20poly1305::Key, String> {
+    use chacha20poly1305::aead::{KeyInit, OsRng};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    let entry = keyring::Entry::new(KEYRING_SERVICE, SETTINGS_KEY_ACCOUNT).map_err(|e| e.to_string())?;
+    match entry.get_password() {
+        Ok(encoded) => {
+            let bytes = Zeroizing::new(
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded.as_bytes())
+                    .map_err(|e| format!("The settings key in the keychain is damaged: {}", e))?,
+            );
+            if bytes.len() != 32 {
+                return Err(String::from("The settings key in the keychain is damaged"));
+            }
+            Ok(*chacha20poly1305::Key::from_slice(&bytes))
+        }
+        Err(keyring::Error::NoEntry) if create => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            let encoded = Zeroizing::new(base64::engine::general_purpose::STANDARD.encode(key));
+            entry.set_password(&encoded).map_err(|e| e.to_string())?;
+            Ok(key)
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Seals the state as base64 of a random nonce followed by the ChaCha20-Poly1305 ciphertext.
+fn encrypt_state(state: &PersistedState) -> Result<String, String> {
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    let cipher = ChaCha20Poly1305::new(&settings_key(true)?);
+    let plaintext = Zeroizing::new(serde_json::to_vec(state).map_err(|e| e.to_string())?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(&nonce, plaintext.as_slice()).map_err(|_| String::from("Encryption failed"))?);
+    Ok(base64::engine::general_purpose::STANDARD.encode(sealed))
+}
+
+fn decrypt_state(sealed: &str) -> Result<PersistedState, String> {
+    use chacha20poly1305::aead::{Aead, KeyInit};
+    use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+    let sealed = base64::engine::general_purpose::STANDARD
+        .decode(sealed.as_bytes())
+        .map_err(|e| format!("The saved settings are damaged: {}", e))?;
+    if sealed.len() < 12 {
+        return Err(String::from("The saved settings are damaged"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    let cipher = ChaCha20Poly1305::new(&settings_key(false)?);
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| String::from("The saved settings do not match the key in the keychain"))?,
+    );
+    serde_json::from_slice(&plaintext).map_err(|e| format!("The saved settings are damaged: {}", e))
+}
+
+/// Comparison and filter settings remembered for one pair of databases. Never holds any key material.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct PairProfile {
+    match_strategy: MatchStrategy,
+    #[serde(default)]
+    match_field: String,
+    include_recycle_bin: bool,
+    compare_groups: bool,
+    compare_history: bool,
+    compare_usage_metadata: bool,
+    ignore_trailing_whitespace: bool,
+    #[serde(default)]
+    strict_presence: bool,
+    ignored_fields: String,
+    #[serde(default)]
+    excluded_groups: String,
+    sort_order: SortOrder,
+    conflicts_only: bool,
+    /// `DifferenceTypeKind::name`s of the kinds filtered out of the list
+    hidden_kinds: Vec<String>,
+}
+
+/// Identifies a pair of databases regardless of which slot each is in, by their canonical paths.
+fn pair_profile_key(path1: &str, path2: &str) -> Option<String> {
+    if path1.is_empty() || path2.is_empty() {
+        return None;
+    }
+    let canonical = |path: &str| match std::fs::canonicalize(path) {
+        Ok(canonical) => canonical.display().to_string(),
+        Err(_) => path.to_string(),
+    };
+    let mut paths = [canonical(path1), canonical(path2)];
+    paths.sort();
+    Some(paths.join("\n"))
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    const ALL: [Theme; 2] = [Theme::Dark, Theme::Light];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Theme::Dark => "🌙 Dark",
+            Theme::Light => "☀ Light",
+        }
+    }
+
+    fn visuals(&self) -> egui::Visuals {
+        match self {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        }
+    }
+}
+
+/// Built-in color sets for difference types.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum PalettePreset {
+    #[default]
+    Standard,
+    /// The Okabe-Ito colors, which stay distinguishable with the common forms of color blindness
+    ColorblindSafe,
+}
+
+impl PalettePreset {
+    const ALL: [PalettePreset; 2] = [PalettePreset::Standard, PalettePreset::ColorblindSafe];
+
+    fn label(&self) -> &'static str {
+        match self {
+            PalettePreset::Standard => "Standard",
+            PalettePreset::ColorblindSafe => "Colorblind-safe",
+        }
+    }
+
+    fn color(&self, kind: DifferenceTypeKind) -> egui::Color32 {
+        match self {
+            PalettePreset::Standard => standard_color(kind),
+            PalettePreset::ColorblindSafe => {
+                const ORANGE: egui::Color32 = egui::Color32::from_rgb(230, 159, 0);
+                const SKY_BLUE: egui::Color32 = egui::Color32::from_rgb(86, 180, 233);
+                const BLUISH_GREEN: egui::Color32 = egui::Color32::from_rgb(0, 158, 115);
+                const YELLOW: egui::Color32 = egui::Color32::from_rgb(240, 228, 66);
+                const BLUE: egui::Color32 = egui::Color32::from_rgb(0, 114, 178);
+                const VERMILLION: egui::Color32 = egui::Color32::from_rgb(213, 94, 0);
+                const REDDISH_PURPLE: egui::Color32 = egui::Color32::from_rgb(204, 121, 167);
+                const GREY: egui::Color32 = egui::Color32::from_rgb(153, 153, 153);
+                match kind {
+                    DifferenceTypeKind::OnlyInOne | DifferenceTypeKind::OnlyInTwo => ORANGE,
+                    DifferenceTypeKind::GroupOnlyInOne | DifferenceTypeKind::GroupOnlyInTwo => ORANGE,
+                    DifferenceTypeKind::TitleDiffers | DifferenceTypeKind::NotesDiffers => SKY_BLUE,
+                    DifferenceTypeKind::UsernameDiffers | DifferenceTypeKind::UrlDiffers => BLUE,
+                    DifferenceTypeKind::PasswordDiffers | DifferenceTypeKind::TotpDiffers => VERMILLION,
+                    DifferenceTypeKind::CustomFieldDiffers | DifferenceTypeKind::AttachmentsDiffer => REDDISH_PURPLE,
+                    DifferenceTypeKind::EntryMoved | DifferenceTypeKind::GroupMetadataDiffers => YELLOW,
+                    DifferenceTypeKind::DanglingReference => YELLOW,
+                    DifferenceTypeKind::ExpiryDiffers | DifferenceTypeKind::TagsDiffers => BLUISH_GREEN,
+                    DifferenceTypeKind::IconDiffers | DifferenceTypeKind::AutoTypeDiffers => BLUISH_GREEN,
+                    DifferenceTypeKind::ProtectionDiffers | DifferenceTypeKind::PresenceDiffers => REDDISH_PURPLE,
+                    DifferenceTypeKind::HistoryDiffers | DifferenceTypeKind::MetadataDiffers => GREY,
+                }
+            }
+        }
+    }
+}
+
+/// The colors of the standard palette, picked to stand out on the dark theme.
+fn standard_color(kind: DifferenceTypeKind) -> egui::Color32 {
+    match kind {
+        DifferenceTypeKind::OnlyInOne | DifferenceTypeKind::OnlyInTwo => egui::Color32::YELLOW,
+        DifferenceTypeKind::TitleDiffers => egui::Color32::LIGHT_GREEN,
+        DifferenceTypeKind::UsernameDiffers => egui::Color32::LIGHT_BLUE,
+        DifferenceTypeKind::PasswordDiffers => egui::Color32::RED,
+        DifferenceTypeKind::UrlDiffers => egui::Color32::GOLD,
+        DifferenceTypeKind::NotesDiffers => egui::Color32::LIGHT_YELLOW,
+        DifferenceTypeKind::CustomFieldDiffers => egui::Color32::LIGHT_GRAY,
+        DifferenceTypeKind::TotpDiffers => egui::Color32::LIGHT_RED,
+        DifferenceTypeKind::GroupOnlyInOne | DifferenceTypeKind::GroupOnlyInTwo => egui::Color32::ORANGE,
+        DifferenceTypeKind::EntryMoved => egui::Color32::KHAKI,
+        DifferenceTypeKind::ExpiryDiffers => egui::Color32::from_rgb(200, 160, 255),
+        DifferenceTypeKind::TagsDiffers => egui::Color32::from_rgb(120, 200, 200),
+        DifferenceTypeKind::AttachmentsDiffer => egui::Color32::from_rgb(255, 180, 120),
+        DifferenceTypeKind::HistoryDiffers => egui::Color32::from_rgb(170, 170, 220),
+        DifferenceTypeKind::IconDiffers => egui::Color32::from_rgb(200, 170, 120),
+        DifferenceTypeKind::MetadataDiffers => egui::Color32::from_rgb(150, 150, 150),
+        DifferenceTypeKind::ProtectionDiffers => egui::Color32::from_rgb(130, 190, 255),
+        DifferenceTypeKind::PresenceDiffers => egui::Color32::from_rgb(190, 190, 240),
+        DifferenceTypeKind::GroupMetadataDiffers => egui::Color32::from_rgb(255, 200, 140),
+        DifferenceTypeKind::AutoTypeDiffers => egui::Color32::from_rgb(180, 220, 150),
+        DifferenceTypeKind::DanglingReference => egui::Color32::from_rgb(255, 110, 60),
+    }
+}
+
+/// The colors difference types are shown in: a preset, plus any colors the user picked by hand.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct DifferencePalette {
+    preset: PalettePreset,
+    /// Hand-picked colors keyed by `DifferenceTypeKind::name`, so they survive kinds being added or reordered
+    custom: BTreeMap<String, [u8; 3]>,
 }
 
-#[derive(Clone)]
-struct DifferenceInfo {
-    title: String,
-    username: String,
-    diff_type: DifferenceType,
+impl DifferencePalette {
+    fn color(&self, kind: DifferenceTypeKind) -> egui::Color32 {
+        match self.custom.get(kind.name()) {
+            Some([r, g, b]) => egui::Color32::from_rgb(*r, *g, *b),
+            None => self.preset.color(kind),
+        }
+    }
+}
+
+impl Default for RustPassApp {
+    fn default() -> Self {
+        Self {
+            database1_path: String::new(),
+            database1_pass: Zeroizing::default(),
+            database1_keyfile: String::new(),
+            database1_key_element: Zeroizing::default(),
+            database1_credential: CredentialType::default(),
+            database1_yubikey_slot: None,
+            show_pass1: false,
+            remember_pass1: false,
+            database2_path: String::new(),
+            database2_pass: Zeroizing::default(),
+            database2_keyfile: String::new(),
+            database2_key_element: Zeroizing::default(),
+            database2_credential: CredentialType::default(),
+            database2_yubikey_slot: None,
+            show_pass2: false,
+            remember_pass2: false,
+            same_password: false,
+            extra_sources: Vec::new(),
+            snapshot_mode: false,
+            subtree: Vec::new(),
+            focused_target: None,
+            recent_paths: Vec::new(),
+            theme: Theme::default(),
+            palette: DifferencePalette::default(),
+            status_message: String::from("Welcome to RustPass! 🔐"),
+            log_entries: Vec::new(),
+            match_strategy: MatchStrategy::ByUuid,
+            match_field: String::new(),
+            field_names: Vec::new(),
+            include_recycle_bin: false,
+            compare_groups: true,
+            compare_history: false,
+            compare_usage_metadata: false,
+            ignore_trailing_whitespace: false,
+            strict_presence: false,
+            ignored_fields: String::new(),
+            excluded_groups: String::new(),
+            differences: Vec::new(),
+            has_compared: false,
+            hashed_results: false,
+            drift: None,
+            trees: None,
+            identical: Vec::new(),
+            show_identical: false,
+            anonymize_exports: false,
+            show_tree: false,
+            show_timings: false,
+            scroll_to_key: None,
+            filter_text: String::new(),
+            sort_order: SortOrder::default(),
+            conflicts_only: false,
+            authoritative: None,
+            visible_kinds: DifferenceTypeKind::ALL.into_iter().collect(),
+            revealed_fields: HashSet::new(),
+            reveal_deadline: None,
+            clipboard_clears: Vec::new(),
+            sync_receiver: None,
+            sync_cancel: Default::default(),
+            loaded_formats: Default::default(),
+            pending_saved_passwords: Vec::new(),
+            sync_progress: None,
+            last_synced: None,
+            source_names: Default::default(),
+            watcher: None,
+            changed_at: None,
+            databases: None,
+            unlocked: Default::default(),
+            resolution: HashMap::new(),
+            resolution_undo: Vec::new(),
+            resolution_redo: Vec::new(),
+            locked_retry: false,
+            pending_overwrite: None,
+            backup_on_overwrite: true,
+            show_about: false,
+            show_shortcuts: false,
+            show_palette: false,
+            merge_preview: None,
+            pair_profiles: HashMap::new(),
+            encrypt_settings: false,
+            settings_unreadable: false,
+            in_memory: false,
+            profile_paths: Default::default(),
+            compared_pair: None,
+        }
+    }
+}
+
+impl RustPassApp {
+    fn new(cc: &eframe::CreationContext<'_>, in_memory: bool) -> Self {
+        let mut app = Self {
+            in_memory,
+            ..Default::default()
+        };
+        let mut state = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<PersistedState>(storage, eframe::APP_KEY));
+        if let Some(storage) = cc.storage.filter(|_| state.as_ref().is_some_and(|state| state.encrypt_settings)) {
+            app.encrypt_settings = true;
+            let sealed = storage.get_string(ENCRYPTED_STATE_KEY).unwrap_or_default();
+            state = match decrypt_state(&sealed) {
+                Ok(decrypted) => Some(decrypted),
+                Err(e) => {
+                    // Saving now would replace the settings with defaults, so leave them for a later run
+                    app.settings_unreadable = true;
+                    app.log(LogLevel::Error, format!("Could not decrypt the saved settings, which are left untouched: {}", e));
+                    None
+                }
+            };
+        }
+        if let Some(state) = state {
+            app.database1_path = state.database1_path;
+            app.database2_path = state.database2_path;
+            app.recent_paths = state.recent_paths;
+            app.theme = state.theme;
+            app.palette = state.palette;
+            app.show_tree = state.show_tree;
+            app.show_timings = state.show_timings;
+            app.show_identical = state.show_identical;
+            app.anonymize_exports = state.anonymize_exports;
+            app.ignored_fields = state.ignored_fields;
+            app.pair_profiles = state.pair_profiles;
+        }
+        app.load_saved_password(DatabaseTarget::First);
+        app.load_saved_password(DatabaseTarget::Second);
+        cc.egui_ctx.set_visuals(app.theme.visuals());
+        app
+    }
+
+    fn pair_profile(&self) -> PairProfile {
+        PairProfile {
+            match_strategy: self.match_strategy,
+            match_field: self.match_field.clone(),
+            include_recycle_bin: self.include_recycle_bin,
+            compare_groups: self.compare_groups,
+            compare_history: self.compare_history,
+            compare_usage_metadata: self.compare_usage_metadata,
+            ignore_trailing_whitespace: self.ignore_trailing_whitespace,
+            strict_presence: self.strict_presence,
+            ignored_fields: self.ignored_fields.clone(),
+            excluded_groups: self.excluded_groups.clone(),
+            sort_order: self.sort_order,
+            conflicts_only: self.conflicts_only,
+            hidden_kinds: DifferenceTypeKind::ALL
+                .into_iter()
+                .filter(|kind| !self.visible_kinds.contains(kind))
+                .map(|kind| kind.name().to_string())
+                .collect(),
+        }
+    }
+
+    fn apply_pair_profile(&mut self, profile: PairProfile) {
+        self.match_strategy = profile.match_strategy;
+        self.match_field = profile.match_field;
+        self.include_recycle_bin = profile.include_recycle_bin;
+        self.compare_groups = profile.compare_groups;
+        self.compare_history = profile.compare_history;
+        self.compare_usage_metadata = profile.compare_usage_metadata;
+        self.ignore_trailing_whitespace = profile.ignore_trailing_whitespace;
+        self.strict_presence = profile.strict_presence;
+        self.ignored_fields = profile.ignored_fields;
+        self.excluded_groups = profile.excluded_groups;
+        self.sort_order = profile.sort_order;
+        self.conflicts_only = profile.conflicts_only;
+        self.visible_kinds = DifferenceTypeKind::ALL
+            .into_iter()
+            .filter(|kind| !profile.hidden_kinds.iter().any(|name| name == kind.name()))
+            .collect();
+    }
+
+    /// Records the current settings for the two databases last compared, after comparing them or when quitting.
+    fn remember_pair_profile(&mut self) {
+        if let Some(key) = self.compared_pair.clone() {
+            self.pair_profiles.insert(key, self.pair_profile());
+        }
+    }
+
+    /// Puts back the settings last used with the two databases once both paths name a known pair.
+    fn restore_pair_profile(&mut self) {
+        if self.profile_paths.0 == self.database1_path && self.profile_paths.1 == self.database2_path {
+            return;
+        }
+        self.profile_paths = (self.database1_path.clone(), self.database2_path.clone());
+        let profile = pair_profile_key(&self.database1_path, &self.database2_path)
+            .and_then(|key| self.pair_profiles.get(&key).cloned());
+        if let Some(profile) = profile.filter(|profile| *profile != self.pair_profile()) {
+            self.apply_pair_profile(profile);
+            self.log(LogLevel::Info, "Restored the settings last used with these databases");
+        }
+    }
+
+    fn remember_path(&mut self, path: &str) {
+        if path.is_empty() {
+            return;
+        }
+        self.recent_paths.retain(|p| p != path);
+        self.recent_paths.insert(0, path.to_string());
+        self.recent_paths.truncate(MAX_RECENT_PATHS);
+    }
+
+    fn browse_file(&mut self, target: DatabaseTarget) {
+        if let Some(path) = pick_database_file() {
+            let path_str = path.display().to_string();
+            self.remember_path(&path_str);
+            match target {
+                DatabaseTarget::First => self.database1_path = path_str,
+                DatabaseTarget::Second => self.database2_path = path_str,
+            }
+            self.log(LogLevel::Info, format!("Selected: {}", path.display()));
+            self.load_saved_password(target);
+        }
+    }
+
+    /// Pre-fills the password saved in the OS keychain for the database's current path, if any.
+    fn load_saved_password(&mut self, target: DatabaseTarget) {
+        let (path, pass, remember) = match target {
+            DatabaseTarget::First => (&self.database1_path, &mut self.database1_pass, &mut self.remember_pass1),
+            DatabaseTarget::Second => (&self.database2_path, &mut self.database2_pass, &mut self.remember_pass2),
+        };
+        if let Some(saved) = saved_password(path) {
+            *pass = saved;
+            *remember = true;
+        }
+    }
+
+    fn forget_saved_password(&mut self, target: DatabaseTarget) {
+        let (path, remember) = match target {
+            DatabaseTarget::First => (&self.database1_path, &mut self.remember_pass1),
+            DatabaseTarget::Second => (&self.database2_path, &mut self.remember_pass2),
+        };
+        *remember = false;
+        match forget_password(path) {
+            Ok(()) => self.log(LogLevel::Info, format!("Removed the saved password for {} from the keychain", path)),
+            Err(e) => self.log(LogLevel::Error, format!("Failed to remove the saved password: {}", e)),
+        }
+    }
+
+    fn browse_keyfile(&mut self, target: DatabaseTarget) {
+        if let Some(path) = rfd::FileDialog::new().pick_file() {
+            let path_str = path.display().to_string();
+            match target {
+                DatabaseTarget::First => self.database1_keyfile = path_str,
+                DatabaseTarget::Second => self.database2_keyfile = path_str,
+            }
+            self.log(LogLevel::Info, format!("Selected key file: {}", path.display()));
+        }
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("file_drop")));
+            let rect = ctx.content_rect();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(192));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop .kdbx or .kdb files to load them",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                egui::Color32::WHITE,
+            );
+        }
+
+        let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|f| f.path.clone()).collect());
+        if dropped.is_empty() {
+            return;
+        }
+
+        let (databases, rejected): (Vec<PathBuf>, Vec<PathBuf>) = dropped
+            .into_iter()
+            .partition(|path| has_database_extension(path));
+        if !rejected.is_empty() {
+            self.log(
+                LogLevel::Warning,
+                format!("Ignored {} dropped file(s) that are not KeePass databases", rejected.len()),
+            );
+        }
+
+        match databases.as_slice() {
+            [] => {}
+            [path] => {
+                let target = if self.database1_path.is_empty() {
+                    DatabaseTarget::First
+                } else if self.database2_path.is_empty() {
+                    DatabaseTarget::Second
+                } else {
+                    self.focused_target.unwrap_or(DatabaseTarget::First)
+                };
+                let path_str = path.display().to_string();
+                match target {
+                    DatabaseTarget::First => self.database1_path = path_str,
+                    DatabaseTarget::Second => self.database2_path = path_str,
+                }
+                self.log(LogLevel::Info, format!("Selected: {}", path.display()));
+                self.load_saved_password(target);
+            }
+            [first, second, ..] => {
+                self.database1_path = first.display().to_string();
+                self.database2_path = second.display().to_string();
+                self.log(LogLevel::Info, format!("Selected: {} and {}", first.display(), second.display()));
+                self.load_saved_password(DatabaseTarget::First);
+                self.load_saved_password(DatabaseTarget::Second);
+            }
+        }
+    }
+
+    /// Indices of the differences that pass the current filters, in display order.
+    fn visible_differences(&self) -> Vec<usize> {
+        let filter = self.filter_text.to_lowercase();
+        let mut visible: Vec<usize> = (0..self.differences.len())
+            .filter(|&i| {
+                let diff = &self.differences[i];
+                let kind = diff.diff_type.kind();
+                self.visible_kinds.contains(&kind)
+                    && (!self.conflicts_only || kind.is_conflict())
+                    && diff.matches_filter(&filter)
+            })
+            .collect();
+        // Stable sorts with the entry key as tie-breaker keep an entry's differences together
+        match self.sort_order {
+            SortOrder::Title => visible.sort_by_cached_key(|&i| {
+                let diff = &self.differences[i];
+                (diff.title.to_lowercase(), diff.key.clone())
+            }),
+            SortOrder::GroupPath => visible.sort_by_cached_key(|&i| {
+                let diff = &self.differences[i];
+                (
+                    diff.group_path.to_lowercase(),
+                    diff.title.to_lowercase(),
+                    diff.key.clone(),
+                )
+            }),
+            SortOrder::DifferenceType => visible.sort_by_cached_key(|&i| {
+                let diff = &self.differences[i];
+                (diff.diff_type.kind(), diff.title.to_lowercase(), diff.key.clone())
+            }),
+        }
+        visible
+    }
+
+    /// The password the second database is unlocked with, which is the first one's while they are the same.
+    fn second_password(&self) -> &Zeroizing<String> {
+        if self.same_password {
+            &self.database1_pass
+        } else {
+            &self.database2_pass
+        }
+    }
+
+    fn sync_job(&self) -> SyncJob {
+        let (database1_pass, database1_keyfile, database1_key_element) =
+            self.database1_credential
+                .select(&self.database1_pass, &self.database1_keyfile, &self.database1_key_element);
+        let (database2_pass, database2_keyfile, database2_key_element) =
+            self.database2_credential
+                .select(self.second_password(), &self.database2_keyfile, &self.database2_key_element);
+        SyncJob {
+            database1_path: self.database1_path.clone(),
+            database1_pass,
+            database1_keyfile,
+            database1_key_element,
+            database1_yubikey_slot: self.database1_yubikey_slot,
+            database2_path: self.database2_path.clone(),
+            database2_pass,
+            database2_keyfile,
+            database2_key_element,
+            database2_yubikey_slot: self.database2_yubikey_slot,
+            extra_sources: self.extra_sources.iter().map(DatabaseSource::resolved).collect(),
+            options: self.compare_options(),
+            open_attempts: 1,
+            snapshot_mode: self.snapshot_mode,
+            unlocked: Default::default(),
+            cancel: Default::default(),
+        }
+    }
+
+    fn compare_options(&self) -> CompareOptions {
+        CompareOptions {
+            match_strategy: self.match_strategy,
+            match_field: self.match_field.clone(),
+            include_recycle_bin: self.include_recycle_bin,
+            compare_groups: self.compare_groups,
+            compare_history: self.compare_history,
+            compare_usage_metadata: self.compare_usage_metadata,
+            ignore_trailing_whitespace: self.ignore_trailing_whitespace,
+            strict_presence: self.strict_presence,
+            subtree: self.subtree.clone(),
+            ignored_fields: parse_field_list(&self.ignored_fields),
+            excluded_groups: parse_field_list(&self.excluded_groups),
+        }
+    }
+
+    fn sync_databases(&mut self) {
+        if !self.snapshot_mode && is_same_file(&self.database1_path, &self.database2_path) {
+            self.clear_results();
+            self.log(LogLevel::Warning, SAME_FILE_WARNING);
+            return;
+        }
+        self.log(LogLevel::Info, "Decrypting databases...");
+        self.clear_results();
+        // Remember the second path first so the first one ends up most recent
+        if !self.snapshot_mode {
+            self.remember_path(&self.database2_path.clone());
+        }
+        self.remember_path(&self.database1_path.clone());
+
+        let mut job = self.sync_job();
+        self.sync_cancel = Arc::new(AtomicBool::new(false));
+        job.cancel = Arc::clone(&self.sync_cancel);
+        self.source_names = if self.snapshot_mode {
+            [String::from("last snapshot"), file_display_name(&job.database1_path)]
+        } else {
+            [file_display_name(&job.database1_path), file_display_name(&job.database2_path)]
+        };
+        if self.source_names[0] == self.source_names[1] {
+            // Same file name in different folders; fall back to the slot to tell them apart
+            for (number, name) in self.source_names.iter_mut().enumerate() {
+                name.push_str(&format!(" (DB{})", number + 1));
+            }
+        }
+        if self.watcher.is_some() {
+            job.open_attempts = WATCH_OPEN_ATTEMPTS;
+        }
+        // Reuse databases from a failed attempt, unless their path, key or file changed since
+        for (slot, unlocked) in std::mem::take(&mut self.unlocked).into_iter().enumerate() {
+            job.unlocked[slot] = unlocked.filter(|unlocked| unlocked.fingerprint == job.unlock_fingerprint(slot));
+        }
+
+        self.pending_saved_passwords.clear();
+        for (remember, path, pass) in [
+            (self.remember_pass1, &job.database1_path, &job.database1_pass),
+            (self.remember_pass2 && !self.snapshot_mode, &job.database2_path, &job.database2_pass),
+        ] {
+            if remember && !pass.is_empty() && !self.in_memory {
+                self.pending_saved_passwords.push((path.clone(), pass.clone()));
+            }
+        }
+
+        // Key derivation can take seconds, so keep it off the UI thread
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver is gone if the app closed mid-sync; nothing left to report to
+            let outcome = job.run(&|progress| {
+                let _ = sender.send(SyncMessage::Progress(progress));
+            });
+            if outcome.is_err() {
+                let _ = sender.send(SyncMessage::Unlocked(Box::new(std::mem::take(&mut job.unlocked))));
+            }
+            let _ = sender.send(SyncMessage::Finished(Box::new(outcome)));
+        });
+        self.sync_receiver = Some(receiver);
+        self.sync_progress = None;
+    }
+
+    fn swap_databases(&mut self) {
+        std::mem::swap(&mut self.database1_path, &mut self.database2_path);
+        // The first password is shared and stays put; the hidden second one must not take its place
+        if !self.same_password {
+            std::mem::swap(&mut self.database1_pass, &mut self.database2_pass);
+        }
+        std::mem::swap(&mut self.database1_keyfile, &mut self.database2_keyfile);
+        std::mem::swap(&mut self.database1_key_element, &mut self.database2_key_element);
+        std::mem::swap(&mut self.database1_credential, &mut self.database2_credential);
+        std::mem::swap(&mut self.database1_yubikey_slot, &mut self.database2_yubikey_slot);
+        std::mem::swap(&mut self.show_pass1, &mut self.show_pass2);
+        std::mem::swap(&mut self.remember_pass1, &mut self.remember_pass2);
+        self.authoritative = self.authoritative.map(DatabaseTarget::other);
+        // Results still name the old order, so compare again rather than show them mislabeled
+        let had_compared = self.has_compared;
+        self.clear_results();
+        if had_compared && self.can_sync() {
+            self.sync_databases();
+        } else {
+            self.log(LogLevel::Info, "Swapped the first and second database");
+        }
+    }
+
+    /// Drops the previous comparison so its decrypted values are wiped instead of lingering.
+    fn clear_results(&mut self) {
+        self.remember_pair_profile();
+        self.differences.clear();
+        self.has_compared = false;
+        self.hashed_results = false;
+        self.drift = None;
+        self.trees = None;
+        self.identical.clear();
+        self.scroll_to_key = None;
+        self.revealed_fields.clear();
+        self.resolution.clear();
+        self.resolution_undo.clear();
+        self.resolution_redo.clear();
+        self.merge_preview = None;
+        self.compared_pair = None;
+        self.databases = None;
+    }
+
+    /// Forgets every path, key and result, keeping only preferences, view filters, recent paths and pending
+    /// clipboard clears.
+    fn clear_all(&mut self) {
+        self.remember_pair_profile();
+        self.sync_cancel.store(true, AtomicOrdering::Relaxed);
+        // Dropping the old state wipes its passwords and decrypted values
+        let previous = std::mem::take(self);
+        self.recent_paths = previous.recent_paths;
+        self.theme = previous.theme;
+        self.palette = previous.palette;
+        self.show_tree = previous.show_tree;
+        self.show_timings = previous.show_timings;
+        self.show_identical = previous.show_identical;
+        self.anonymize_exports = previous.anonymize_exports;
+        self.match_strategy = previous.match_strategy;
+        self.match_field = previous.match_field;
+        self.include_recycle_bin = previous.include_recycle_bin;
+        self.compare_groups = previous.compare_groups;
+        self.compare_history = previous.compare_history;
+        self.compare_usage_metadata = previous.compare_usage_metadata;
+        self.ignore_trailing_whitespace = previous.ignore_trailing_whitespace;
+        self.strict_presence = previous.strict_presence;
+        self.ignored_fields = previous.ignored_fields;
+        self.excluded_groups = previous.excluded_groups;
+        self.filter_text = previous.filter_text;
+        self.sort_order = previous.sort_order;
+        self.conflicts_only = previous.conflicts_only;
+        self.visible_kinds = previous.visible_kinds;
+        self.authoritative = previous.authoritative;
+        self.snapshot_mode = previous.snapshot_mode;
+        self.clipboard_clears = previous.clipboard_clears;
+        self.pair_profiles = previous.pair_profiles;
+        self.encrypt_settings = previous.encrypt_settings;
+        self.settings_unreadable = previous.settings_unreadable;
+        self.in_memory = previous.in_memory;
+    }
+
+    /// Gives all `keys` the same resolution as one undoable step.
+    fn set_resolution(&mut self, keys: Vec<EntryId>, resolution: Resolution) {
+        if keys.is_empty() {
+            return;
+        }
+        self.merge_preview = None;
+        let entries = keys
+            .into_iter()
+            .map(|key| {
+                let before = self.resolution.insert(key.clone(), resolution);
+                (key, before, Some(resolution))
+            })
+            .collect();
+        self.resolution_undo.push(ResolutionChange { entries });
+        if self.resolution_undo.len() > MAX_UNDO_STEPS {
+            self.resolution_undo.remove(0);
+        }
+        self.resolution_redo.clear();
+    }
+
+    /// Puts back the resolutions entries had before the change, returning the change reversed.
+    fn apply_resolution_change(&mut self, change: ResolutionChange) -> ResolutionChange {
+        self.merge_preview = None;
+        for (key, before, _) in &change.entries {
+            match before {
+                Some(resolution) => self.resolution.insert(key.clone(), *resolution),
+                None => self.resolution.remove(key),
+            };
+        }
+        ResolutionChange {
+            entries: change.entries.into_iter().map(|(key, before, after)| (key, after, before)).collect(),
+        }
+    }
+
+    fn undo_resolution(&mut self) {
+        let Some(change) = self.resolution_undo.pop() else {
+            return;
+        };
+        let reversed = self.apply_resolution_change(change);
+        self.log(LogLevel::Info, format!("Undid the resolution choice for {}", self.change_subject(&reversed)));
+        self.resolution_redo.push(reversed);
+    }
+
+    fn redo_resolution(&mut self) {
+        let Some(change) = self.resolution_redo.pop() else {
+            return;
+        };
+        let reversed = self.apply_resolution_change(change);
+        self.log(LogLevel::Info, format!("Redid the resolution choice for {}", self.change_subject(&reversed)));
+        self.resolution_undo.push(reversed);
+    }
+
+    /// Names the entry a change affected, or how many entries for a bulk change.
+    fn change_subject(&self, change: &ResolutionChange) -> String {
+        match change.entries.as_slice() {
+            [(key, _, _)] => self
+                .differences
+                .iter()
+                .find(|diff| diff.key == *key)
+                .map_or_else(|| key.to_string(), |diff| format!("\"{}\"", diff.title)),
+            entries => format!("{} entries", entries.len()),
+        }
+    }
+
+    fn can_sync(&self) -> bool {
+        let has_key1 = self
+            .database1_credential
+            .has_key(&self.database1_pass, &self.database1_keyfile, &self.database1_key_element)
+            || self.database1_yubikey_slot.is_some();
+        let has_key2 = self
+            .database2_credential
+            .has_key(self.second_password(), &self.database2_keyfile, &self.database2_key_element)
+            || self.database2_yubikey_slot.is_some();
+        // A snapshot comparison only ever reads the first database
+        let others_ready = self.snapshot_mode
+            || (!self.database2_path.is_empty()
+                && path_problem(&self.database2_path).is_none()
+                && has_key2
+                && self.extra_sources.iter().all(DatabaseSource::is_ready));
+        !self.database1_path.is_empty()
+            && path_problem(&self.database1_path).is_none()
+            && has_key1
+            && others_ready
+            && !self.is_syncing()
+    }
+
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT_BROWSE_FIRST)) {
+            self.browse_file(DatabaseTarget::First);
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT_BROWSE_SECOND)) {
+            self.browse_file(DatabaseTarget::Second);
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT_SYNC)) && self.can_sync() {
+            self.sync_databases();
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT_CLEAR)) {
+            self.clear_all();
+        }
+        // Text fields have their own undo, so leave the keys to them while one is focused
+        if !ctx.wants_keyboard_input() {
+            if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT_UNDO)) {
+                self.undo_resolution();
+            }
+            if ctx.input_mut(|i| i.consume_shortcut(&SHORTCUT_REDO)) {
+                self.redo_resolution();
+            }
+        }
+    }
+
+    fn is_syncing(&self) -> bool {
+        self.sync_receiver.is_some()
+    }
+
+    /// Shows `message` in the status line and adds it to the log. Messages must never contain secrets.
+    fn log(&mut self, level: LogLevel, message: impl Into<String>) {
+        let message = message.into();
+        self.log_entries.push(LogEntry {
+            at: Local::now(),
+            level,
+            message: message.clone(),
+        });
+        if self.log_entries.len() > MAX_LOG_ENTRIES {
+            self.log_entries.remove(0);
+        }
+        self.status_message = message;
+    }
+
+    /// Stops waiting for the running sync and tells its worker to give up; whatever it was in the
+    /// middle of is thrown away and the previous results stay.
+    fn cancel_sync(&mut self) {
+        if !self.is_syncing() {
+            return;
+        }
+        self.sync_cancel.store(true, AtomicOrdering::Relaxed);
+        self.sync_receiver = None;
+        self.sync_progress = None;
+        self.pending_saved_passwords.clear();
+        self.log(LogLevel::Info, CANCELLED_MESSAGE);
+    }
+
+    fn poll_sync(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.sync_receiver else {
+            return;
+        };
+
+        let result = loop {
+            match receiver.try_recv() {
+                Ok(SyncMessage::Progress(progress)) => {
+                    self.status_message = progress.label();
+                    self.sync_progress = Some(progress);
+                }
+                Ok(SyncMessage::Unlocked(unlocked)) => self.unlocked = *unlocked,
+                Ok(SyncMessage::Finished(result)) => break *result,
+                Err(TryRecvError::Empty) => {
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                    return;
+                }
+                // The worker panicked; report it instead of taking the UI down with it
+                Err(TryRecvError::Disconnected) => break Err(String::from("Comparison stopped unexpectedly")),
+            }
+        };
+        self.sync_receiver = None;
+        self.sync_progress = None;
+        self.locked_retry = matches!(&result, Err(e) if e.contains(LOCKED_FILE_MESSAGE));
+
+        match result {
+            Ok(outcome) => {
+                self.differences = outcome.differences;
+                self.has_compared = true;
+                self.hashed_results = outcome.snapshot.is_some();
+                self.last_synced = Some(Local::now());
+                self.drift = outcome.drift;
+                self.trees = Some(outcome.trees);
+                self.identical = outcome.identical;
+                self.field_names = outcome.field_names;
+                self.databases = outcome.databases;
+                let paths = [&self.database1_path, &self.database2_path];
+                for (((loaded, format), weak_kdf), path) in
+                    self.loaded_formats.iter_mut().zip(&outcome.formats).zip(&outcome.weak_kdfs).zip(paths)
+                {
+                    *loaded = format.clone().map(|format| (path.clone(), format, weak_kdf.clone()));
+                }
+                if !self.snapshot_mode {
+                    self.compared_pair = pair_profile_key(&self.database1_path, &self.database2_path);
+                    self.remember_pair_profile();
+                }
+
+                let mut level = LogLevel::Info;
+                let mut message = match &outcome.snapshot {
+                    Some(SnapshotStatus::First) => format!(
+                        "Took a first snapshot of {} entries; the next comparison shows what changed since",
+                        outcome.entry_count2
+                    ),
+                    Some(SnapshotStatus::TakenAt(taken_at)) => format!(
+                        "Compared with the snapshot from {}\nThen: {} entries\nNow: {} entries\nChanges found: {}",
+                        taken_at.format("%Y-%m-%d %H:%M:%S"),
+                        outcome.entry_count1,
+                        outcome.entry_count2,
+                        self.differences.len()
+                    ),
+                    None => format!(
+                        "Successfully compared databases!\nDatabase 1: {} entries\nDatabase 2: {} entries\nDifferences found: {}",
+                        outcome.entry_count1,
+                        outcome.entry_count2,
+                        self.differences.len()
+                    ),
+                };
+                if outcome.snapshot.is_none() && outcome.entry_count1 == 0 && outcome.entry_count2 == 0 {
+                    message.push_str("\nBoth databases contain no entries");
+                }
+                if let [Some(format1), Some(format2)] = &outcome.formats {
+                    if format1 != format2 {
+                        message.push_str(&format!(
+                            "\nℹ DB1 is {} and DB2 is {}; custom data and some key derivation settings exist only in KDBX 4",
+                            format1, format2
+                        ));
+                    }
+                }
+                for (index, weak_kdf) in outcome.weak_kdfs.iter().enumerate() {
+                    if let Some(weak_kdf) = weak_kdf {
+                        level = LogLevel::Warning;
+                        message.push_str(&format!(
+                            "\n⚠ DB{} uses {}; its protection is below the recommended strength",
+                            index + 1,
+                            weak_kdf
+                        ));
+                    }
+                }
+                for (index, count) in outcome.uncompared_attachments.iter().enumerate() {
+                    if *count > 0 {
+                        message.push_str(&format!(
+                            "\nℹ DB{} has {} attachments, which are not compared; Rustpass can't yet tell which entry each belongs to",
+                            index + 1,
+                            count
+                        ));
+                    }
+                }
+                if outcome.key_fallbacks > 0 {
+                    level = LogLevel::Warning;
+                    message.push_str(&format!(
+                        "\n⚠ {} entries have no \"{}\" field and were matched by title instead",
+                        outcome.key_fallbacks, self.match_field
+                    ));
+                }
+                if self.show_timings {
+                    message.push_str(&format!(
+                        " (decrypted in {:.1}s, compared in {:.1}s)",
+                        outcome.decrypt_time.as_secs_f64(),
+                        outcome.compare_time.as_secs_f64()
+                    ));
+                }
+
+                // Only passwords that just unlocked their database are worth keeping
+                for (path, pass) in std::mem::take(&mut self.pending_saved_passwords) {
+                    if let Err(e) = save_password(&path, &pass) {
+                        level = LogLevel::Warning;
+                        message.push_str(&format!("\nFailed to save the password in the keychain: {}", e));
+                    }
+                }
+                self.log(level, message);
+            }
+            Err(e) => {
+                self.pending_saved_passwords.clear();
+                let mut message = e;
+                for (number, unlocked) in self.unlocked.iter().enumerate() {
+                    if unlocked.is_some() {
+                        message.push_str(&format!(
+                            "\nDB{} opened fine and stays unlocked until its path or key changes",
+                            number + 1
+                        ));
+                    }
+                }
+                if self.same_password && !self.snapshot_mode && self.unlocked[0].is_some() && self.unlocked[1].is_none() {
+                    message.push_str("\nDB2 was tried with DB1's password; untick \"Same as first\" if it has its own");
+                }
+                self.log(LogLevel::Error, message);
+            }
+        }
+    }
+
+    /// Forgets a database kept from a failed sync as soon as its path or key is edited.
+    fn drop_stale_unlocked(&mut self) {
+        if self.unlocked.iter().all(Option::is_none) {
+            return;
+        }
+        let job = self.sync_job();
+        for (slot, unlocked) in self.unlocked.iter_mut().enumerate() {
+            if unlocked.as_ref().is_some_and(|unlocked| unlocked.fingerprint != job.unlock_fingerprint(slot)) {
+                *unlocked = None;
+            }
+        }
+    }
+
+    fn toggle_watch(&mut self, ctx: &egui::Context) {
+        if self.watcher.take().is_some() {
+            self.changed_at = None;
+            self.log(LogLevel::Info, "Stopped watching the databases");
+            return;
+        }
+        let second = if self.snapshot_mode { &self.database1_path } else { &self.database2_path };
+        match FileWatcher::new(ctx, [&self.database1_path, second]) {
+            Ok(watcher) => {
+                self.watcher = Some(watcher);
+                self.log(LogLevel::Info, "Watching both databases; they are compared again whenever one changes");
+            }
+            Err(e) => self.log(LogLevel::Error, e),
+        }
+    }
+
+    /// Re-runs the comparison once a watched file has stopped changing for `WATCH_DEBOUNCE`.
+    fn poll_watch(&mut self, ctx: &egui::Context) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        if watcher.changed() {
+            self.changed_at = Some(Instant::now());
+        }
+        let Some(changed_at) = self.changed_at else {
+            return;
+        };
+        let quiet_for = changed_at.elapsed();
+        if quiet_for < WATCH_DEBOUNCE || self.is_syncing() {
+            ctx.request_repaint_after(WATCH_DEBOUNCE.saturating_sub(quiet_for).max(Duration::from_millis(100)));
+            return;
+        }
+        self.changed_at = None;
+        if self.can_sync() {
+            self.sync_databases();
+        }
+    }
+
+    fn copy_to_clipboard(&mut self, ctx: &egui::Context, value: String, secret: bool, description: String) {
+        ctx.copy_text(value);
+        if secret {
+            self.log(
+                LogLevel::Info,
+                format!(
+                    "Copied {} to the clipboard; it will be cleared in {} seconds",
+                    description,
+                    CLIPBOARD_CLEAR_DELAY.as_secs()
+                ),
+            );
+            self.clipboard_clears.push((Instant::now() + CLIPBOARD_CLEAR_DELAY, description));
+        } else {
+            // The clipboard no longer holds the secret, so there is nothing left to clear
+            self.clipboard_clears.clear();
+            self.log(LogLevel::Info, format!("Copied {} to the clipboard", description));
+        }
+    }
+
+    fn poll_reveal_timeout(&mut self, ctx: &egui::Context) {
+        if self.revealed_fields.is_empty() {
+            self.reveal_deadline = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let interacted = ctx.input(|i| {
+            i.pointer.any_pressed()
+                || i.events.iter().any(|e| matches!(e, egui::Event::Key { .. } | egui::Event::MouseWheel { .. }))
+        });
+        let deadline = match self.reveal_deadline {
+            Some(deadline) if !interacted => deadline,
+            _ => now + REVEAL_TIMEOUT,
+        };
+
+        if deadline <= now {
+            self.revealed_fields.clear();
+            self.reveal_deadline = None;
+        } else {
+            self.reveal_deadline = Some(deadline);
+            ctx.request_repaint_after(deadline - now);
+        }
+    }
+
+    fn poll_clipboard_clears(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        if let Some((_, description)) = self.clipboard_clears.iter().find(|(deadline, _)| *deadline <= now) {
+            ctx.copy_text(String::new());
+            self.log(LogLevel::Info, format!("Cleared {} from the clipboard", description));
+            self.clipboard_clears.retain(|(deadline, _)| *deadline > now);
+        }
+
+        if let Some(next) = self.clipboard_clears.iter().map(|(deadline, _)| *deadline).min() {
+            ctx.request_repaint_after(next - now);
+        }
+    }
+
+    /// The differences as written to reports, anonymized when asked to.
+    fn exported_differences(&self) -> Cow<'_, [Difference]> {
+        if self.anonymize_exports {
+            Cow::Owned(anonymize(&self.differences))
+        } else {
+            Cow::Borrowed(&self.differences)
+        }
+    }
+
+    fn export_csv(&mut self) {
+        if self.in_memory {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("CSV", &["csv"])
+            .set_file_name("differences.csv")
+            .save_file()
+        else {
+            return;
+        };
+
+        match std::fs::write(&path, differences_to_csv(&self.exported_differences())) {
+            Ok(()) => self.log(
+                LogLevel::Info,
+                format!("Exported {} differences to {}", self.differences.len(), path.display()),
+            ),
+            Err(e) => self.log(LogLevel::Error, format!("Error exporting CSV: {}", e)),
+        }
+    }
+
+    fn export_html(&mut self) {
+        if self.in_memory {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("HTML", &["html"])
+            .set_file_name("differences.html")
+            .save_file()
+        else {
+            return;
+        };
+
+        let html = differences_to_html(&self.exported_differences(), |kind| {
+            let color = self.palette.color(kind);
+            [color.r(), color.g(), color.b()]
+        });
+        match std::fs::write(&path, html) {
+            Ok(()) => self.log(
+                LogLevel::Info,
+                format!("Exported {} differences to {}", self.differences.len(), path.display()),
+            ),
+            Err(e) => self.log(LogLevel::Error, format!("Error exporting HTML: {}", e)),
+        }
+    }
+
+    fn export_json(&mut self) {
+        if self.in_memory {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name("differences.json")
+            .save_file()
+        else {
+            return;
+        };
+
+        match differences_to_json(&self.exported_differences())
+            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()))
+        {
+            Ok(()) => self.log(
+                LogLevel::Info,
+                format!("Exported {} differences to {}", self.differences.len(), path.display()),
+            ),
+            Err(e) => self.log(LogLevel::Error, format!("Error exporting JSON: {}", e)),
+        }
+    }
+
+    fn export_settings(&mut self) {
+        if self.in_memory {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name("rustpass-profile.json")
+            .save_file()
+        else {
+            return;
+        };
+
+        match SettingsProfile::of(&self.compare_options())
+            .to_json()
+            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()))
+        {
+            Ok(()) => self.log(LogLevel::Info, format!("Exported the comparison settings to {}", path.display())),
+            Err(e) => self.log(LogLevel::Error, format!("Error exporting settings: {}", e)),
+        }
+    }
+
+    fn import_settings(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).pick_file() else {
+            return;
+        };
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| self.apply_settings_profile(&json))
+        {
+            Ok(()) => self.log(LogLevel::Info, format!("Imported the comparison settings from {}", path.display())),
+            Err(e) => self.log(LogLevel::Error, format!("Error importing settings from {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Replaces the comparison settings with the profile in `json`, or leaves them untouched if it is malformed.
+    fn apply_settings_profile(&mut self, json: &str) -> Result<(), String> {
+        let profile = SettingsProfile::from_json(json)?;
+        self.match_strategy = profile.match_strategy;
+        self.match_field = profile.match_field;
+        self.include_recycle_bin = profile.include_recycle_bin;
+        self.compare_groups = profile.compare_groups;
+        self.compare_history = profile.compare_history;
+        self.compare_usage_metadata = profile.compare_usage_metadata;
+        self.ignore_trailing_whitespace = profile.ignore_trailing_whitespace;
+        self.strict_presence = profile.strict_presence;
+        self.ignored_fields = profile.ignored_fields.join(", ");
+        self.excluded_groups = profile.excluded_groups.join(", ");
+        Ok(())
+    }
+
+    fn preview_merge(&mut self) {
+        let Some((db1, db2, options)) = &self.databases else {
+            return;
+        };
+        let plan = options.merge_plan(db1, db2, &self.resolution, &self.differences);
+        self.log(LogLevel::Info, format!("Review the {} planned actions, then apply and save", plan.len()));
+        self.merge_preview = Some(plan);
+    }
+
+    fn save_merged_database(&mut self) {
+        if self.databases.is_none() || self.in_memory {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("KeePass Database", &["kdbx"])
+            .set_file_name("merged.kdbx")
+            .save_file()
+        else {
+            return;
+        };
+
+        if path.exists() {
+            // Ask first; the dialog calls `write_merged_database` once confirmed
+            self.pending_overwrite = Some(path);
+        } else {
+            self.write_merged_database(&path);
+        }
+    }
+
+    fn write_merged_database(&mut self, path: &Path) {
+        let Some((db1, db2, options)) = &self.databases else {
+            return;
+        };
+        let merged = options.merge_databases(db1, db2, &self.resolution);
+        // The merged database is written with the first database's credentials
+        let job = self.sync_job();
+        let backup = self.backup_on_overwrite && path.exists();
+        match job.save_database(&merged, path, backup) {
+            Ok(Some(backup)) => self.log(
+                LogLevel::Info,
+                format!(
+                    "Saved merged database to {} (previous version kept as {})",
+                    path.display(),
+                    backup.display()
+                ),
+            ),
+            Ok(None) => self.log(LogLevel::Info, format!("Saved merged database to {}", path.display())),
+            Err(e) => self.log(LogLevel::Error, format!("Error saving merged database: {}", e)),
+        }
+    }
+
+    fn help_windows(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut self.show_shortcuts)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcuts").striped(true).show(ui, |ui| {
+                    for (action, shortcut) in SHORTCUTS {
+                        ui.label(action);
+                        ui.monospace(ctx.format_shortcut(&shortcut));
+                        ui.end_row();
+                    }
+                });
+            });
+        egui::Window::new("Difference colors")
+            .open(&mut self.show_palette)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Preset:");
+                    for preset in PalettePreset::ALL {
+                        if ui.radio_value(&mut self.palette.preset, preset, preset.label()).clicked() {
+                            // Picking a preset starts over from its colors
+                            self.palette.custom.clear();
+                        }
+                    }
+                });
+                ui.separator();
+                egui::Grid::new("difference_colors").striped(true).show(ui, |ui| {
+                    for kind in DifferenceTypeKind::ALL {
+                        let [r, g, b, _] = self.palette.color(kind).to_array();
+                        let mut rgb = [r, g, b];
+                        if ui.color_edit_button_srgb(&mut rgb).changed() {
+                            self.palette.custom.insert(kind.name().to_string(), rgb);
+                        }
+                        ui.colored_label(self.palette.color(kind), kind.label());
+                        let customized = self.palette.custom.contains_key(kind.name());
+                        if ui.add_enabled(customized, egui::Button::new("Reset")).clicked() {
+                            self.palette.custom.remove(kind.name());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        egui::Window::new("About RustPass")
+            .open(&mut self.show_about)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.heading(format!("RustPass {}", env!("CARGO_PKG_VERSION")));
+                ui.label("Compare and merge KeePass databases.");
+            });
+    }
+
+    fn overwrite_dialog(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.pending_overwrite.clone() else {
+            return;
+        };
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Overwrite database?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("Overwrite {}?", path.display()));
+                ui.checkbox(&mut self.backup_on_overwrite, "Keep a timestamped .bak copy of the current file");
+                ui.horizontal(|ui| {
+                    cancelled = ui.button("Cancel").clicked();
+                    confirmed = ui.button("Overwrite").clicked();
+                });
+            });
+        if confirmed {
+            self.pending_overwrite = None;
+            self.write_merged_database(&path);
+        } else if cancelled {
+            self.pending_overwrite = None;
+            self.log(LogLevel::Info, "Save cancelled");
+        }
+    }
+}
+
+/// One resolution choice, for a single entry or a bulk action, recording what it replaced so it can be undone.
+struct ResolutionChange {
+    /// Each entry with its resolution before and after the change
+    entries: Vec<(EntryId, Option<Resolution>, Option<Resolution>)>,
+}
+
+/// Most databases compared at once, counting the first and second.
+const MAX_DATABASES: usize = 6;
+
+#[derive(Clone, Default)]
+struct DatabaseSource {
+    path: String,
+    credential: CredentialType,
+    password: Zeroizing<String>,
+    keyfile: String,
+    key_element: Zeroizing<String>,
+    yubikey_slot: Option<u8>,
+}
+
+impl DatabaseSource {
+    fn is_ready(&self) -> bool {
+        let has_key =
+            self.credential.has_key(&self.password, &self.keyfile, &self.key_element) || self.yubikey_slot.is_some();
+        !self.path.is_empty() && path_problem(&self.path).is_none() && has_key
+    }
+
+    /// Copy holding only the credentials its credential type uses.
+    fn resolved(&self) -> Self {
+        let (password, keyfile, key_element) = self.credential.select(&self.password, &self.keyfile, &self.key_element);
+        Self {
+            path: self.path.clone(),
+            credential: self.credential,
+            password,
+            keyfile,
+            key_element,
+            yubikey_slot: self.yubikey_slot,
+        }
+    }
+}
+
+/// How a database is unlocked; a YubiKey can be added on top of any of them.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum CredentialType {
+    #[default]
+    Password,
+    /// A key file, optionally combined with a password
+    KeyFile,
+    /// The 32-byte key-file element, pasted as hex or base64; keepass hashes it with any other key
+    /// parts into the composite key, just like the contents of a 32-byte key file
+    KeyFileElement,
+}
+
+impl CredentialType {
+    const ALL: [CredentialType; 3] = [CredentialType::Password, CredentialType::KeyFile, CredentialType::KeyFileElement];
+
+    fn label(&self) -> &'static str {
+        match self {
+            CredentialType::Password => "Password",
+            CredentialType::KeyFile => "Key file",
+            CredentialType::KeyFileElement => "Key file element",
+        }
+    }
+
+    fn has_key(&self, password: &str, keyfile: &str, key_element: &str) -> bool {
+        match self {
+            CredentialType::Password => !password.is_empty(),
+            CredentialType::KeyFile => !keyfile.is_empty(),
+            CredentialType::KeyFileElement => !key_element.is_empty(),
+        }
+    }
+
+    /// Blanks the values this type doesn't use, so fields hidden in the UI never end up in the key.
+    fn select(
+        &self,
+        password: &Zeroizing<String>,
+        keyfile: &str,
+        key_element: &Zeroizing<String>,
+    ) -> (Zeroizing<String>, String, Zeroizing<String>) {
+        match self {
+            CredentialType::Password => (password.clone(), String::new(), Zeroizing::default()),
+            CredentialType::KeyFile => (password.clone(), keyfile.to_string(), Zeroizing::default()),
+            CredentialType::KeyFileElement => (Zeroizing::default(), String::new(), key_element.clone()),
+        }
+    }
+}
+
+const MAX_LOG_ENTRIES: usize = 200;
+
+#[derive(Clone, Copy, PartialEq)]
+enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    fn color(&self, ui: &egui::Ui) -> egui::Color32 {
+        match self {
+            LogLevel::Info => ui.visuals().text_color(),
+            LogLevel::Warning => ui.visuals().warn_fg_color,
+            LogLevel::Error => ui.visuals().error_fg_color,
+        }
+    }
+}
+
+struct LogEntry {
+    at: DateTime<Local>,
+    level: LogLevel,
+    message: String,
+}
+
+/// Messages sent from the background worker to the UI thread.
+enum SyncMessage {
+    Progress(SyncProgress),
+    /// Databases that opened before the sync failed, kept for the next attempt
+    Unlocked(Box<[Option<UnlockedDatabase>; 2]>),
+    // Boxed so the frequent progress messages stay small
+    Finished(Box<Result<SyncOutcome, String>>),
+}
+
+fn pick_database_file() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("KeePass Database", &DATABASE_EXTENSIONS)
+        .pick_file()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DatabaseTarget {
+    First,
+    Second,
+}
+
+impl DatabaseTarget {
+    fn other(self) -> Self {
+        match self {
+            DatabaseTarget::First => DatabaseTarget::Second,
+            DatabaseTarget::Second => DatabaseTarget::First,
+        }
+    }
+
+    /// Index into per-database arrays such as `source_names`.
+    fn index(self) -> usize {
+        match self {
+            DatabaseTarget::First => 0,
+            DatabaseTarget::Second => 1,
+        }
+    }
 }
 
-#[derive(Clone)]
-enum DifferenceType {
-    OnlyInOne,
-    OnlyInTwo,
-    UsernameDiffers { username1: String, username2: String },
-    PasswordDiffers,
+/// What to call an entry or group found in only one database: where it is, or, with an
+/// authoritative source, whether the copy is missing it or has it extra.
+fn one_sided_label(kind: DifferenceTypeKind, authoritative: Option<DatabaseTarget>, source_names: &[String; 2]) -> String {
+    let side = if matches!(kind, DifferenceTypeKind::OnlyInOne | DifferenceTypeKind::GroupOnlyInOne) { 0 } else { 1 };
+    let Some(master) = authoritative else {
+        let icon = if kind.is_group() { "📁 Group only in" } else { "⚠ Only in" };
+        return format!("{} {}", icon, source_names[side]);
+    };
+    let copy = &source_names[master.other().index()];
+    match (drift(kind, master), kind.is_group()) {
+        (Drift::Missing, false) => format!("⚠ Missing from {}", copy),
+        (Drift::Missing, true) => format!("📁 Group missing from {}", copy),
+        (_, false) => format!("➕ Extra in {}", copy),
+        (_, true) => format!("📁 Extra group in {}", copy),
+    }
 }
 
-impl Default for RustPassApp {
-    fn default() -> Self {
-        Self {
-            database1_path: String::new(),
-            database1_pass: String::new(),
-            database2_path: String::new(),
-            database2_pass: String::new(),
-            status_message: String::from("Welcome to RustPass! 🔐"),
-            differences: Vec::new(),
-        }
-    }
+/// How a difference reads when one database is the master and the other a copy of it.
+#[derive(Clone, Copy, PartialEq)]
+enum Drift {
+    /// In the master but not the copy
+    Missing,
+    /// In the copy but not the master
+    Extra,
+    /// In both, with the copy's values differing from the master's
+    Modified,
 }
 
-impl RustPassApp {
-    fn browse_file(&mut self, target: DatabaseTarget) {
-        if let Some(path) = rfd::FileDialog::new()
-            .add_filter("KeePass Database", &["kdbx"])
-            .pick_file()
-        {
-            let path_str = path.display().to_string();
-            match target {
-                DatabaseTarget::First => self.database1_path = path_str,
-                DatabaseTarget::Second => self.database2_path = path_str,
-            }
-            self.status_message = format!("Selected: {}", path.display());
-        }
+fn drift(kind: DifferenceTypeKind, authoritative: DatabaseTarget) -> Drift {
+    let only_in = match kind {
+        DifferenceTypeKind::OnlyInOne | DifferenceTypeKind::GroupOnlyInOne => DatabaseTarget::First,
+        DifferenceTypeKind::OnlyInTwo | DifferenceTypeKind::GroupOnlyInTwo => DatabaseTarget::Second,
+        _ => return Drift::Modified,
+    };
+    if only_in == authoritative {
+        Drift::Missing
+    } else {
+        Drift::Extra
     }
+}
 
-    fn sync_databases(&mut self) {
-        self.status_message = "Decrypting databases...".to_string();
+/// A small ring that empties clockwise as `fraction` goes from 1 to 0.
+fn countdown_ring(ui: &mut egui::Ui, fraction: f32) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+    let painter = ui.painter();
+    let radius = 6.0;
+    let color = ui.visuals().text_color();
+    painter.circle_stroke(rect.center(), radius, egui::Stroke::new(1.0, ui.visuals().weak_text_color()));
 
-        // Open and decrypt first database
-        let db1 = match self.open_database(&self.database1_path, &self.database1_pass) {
-            Ok(db) => db,
-            Err(e) => {
-                self.status_message = format!("Error opening first database: {}", e);
-                return;
-            }
-        };
+    let steps = 32;
+    let points: Vec<egui::Pos2> = (0..=steps)
+        .map(|i| {
+            let angle = -std::f32::consts::FRAC_PI_2 + std::f32::consts::TAU * fraction * i as f32 / steps as f32;
+            rect.center() + radius * egui::vec2(angle.cos(), angle.sin())
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(2.0, color)));
+}
 
-        // Open and decrypt second database
-        let db2 = match self.open_database(&self.database2_path, &self.database2_pass) {
-            Ok(db) => db,
-            Err(e) => {
-                self.status_message = format!("Error opening second database: {}", e);
-                return;
+/// Picks how a database is unlocked.
+fn credential_selector(ui: &mut egui::Ui, id_salt: impl std::hash::Hash, credential: &mut CredentialType) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(credential.label())
+        .show_ui(ui, |ui| {
+            for option in CredentialType::ALL {
+                ui.selectable_value(credential, option, option.label());
             }
-        };
-
-        // Compare databases
-        self.differences = self.compare_databases(&db1, &db2);
+        });
+}
 
-        self.status_message = format!(
-            "Successfully compared databases!\nDatabase 1: {} entries\nDatabase 2: {} entries\nDifferences found: {}",
-            self.count_entries(&db1),
-            self.count_entries(&db2),
-            self.differences.len()
-        );
+/// Opts a database into YubiKey HMAC-SHA1 challenge-response and picks the slot to challenge.
+fn yubikey_selector(ui: &mut egui::Ui, slot: &mut Option<u8>) {
+    let mut enabled = slot.is_some();
+    if ui.checkbox(&mut enabled, "YubiKey").changed() {
+        *slot = enabled.then_some(2);
     }
-
-    fn open_database(&self, path: &str, password: &str) -> Result<Database, String> {
-        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
-        let key = DatabaseKey::new().with_password(password);
-        Database::open(&mut std::io::BufReader::new(file), key)
-            .map_err(|e| format!("Failed to decrypt database: {}", e))
+    if let Some(selected) = slot {
+        ui.radio_value(selected, 1, "Slot 1");
+        ui.radio_value(selected, 2, "Slot 2");
     }
+}
 
-    fn count_entries(&self, db: &Database) -> usize {
-        db.root.entries().len()
-            + db.root
-                .groups()
-                .iter()
-                .map(|g| self.count_group_entries(g))
-                .sum::<usize>()
-    }
+/// Two-column view of an entry's standard fields in both databases, with differing rows highlighted.
+/// A hashed password stays masked without a Reveal button, as a hash of a weak one is easily reversed.
+fn field_table(ui: &mut egui::Ui, diff: &Difference, revealed_fields: &mut HashSet<usize>, index: usize, hashed: bool) {
+    let fields1 = diff.snapshot1.as_ref().map(|snapshot| snapshot.fields());
+    let fields2 = diff.snapshot2.as_ref().map(|snapshot| snapshot.fields());
+    let Some(names) = fields1.or(fields2) else {
+        return;
+    };
+    egui::Grid::new(("field_table", &diff.key, index))
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Field");
+            ui.strong("DB1");
+            ui.strong("DB2");
+            ui.end_row();
 
-    fn count_group_entries(&self, group: &Group) -> usize {
-        group.entries().len()
-            + group
-                .groups()
-                .iter()
-                .map(|g| self.count_group_entries(g))
-                .sum::<usize>()
-    }
-
-    fn compare_databases(&self, db1: &Database, db2: &Database) -> Vec<DifferenceInfo> {
-        let mut differences = Vec::new();
-
-        // Build maps of entries with title as key
-        let entries1 = self.collect_all_entries(&db1.root);
-        let entries2 = self.collect_all_entries(&db2.root);
-
-        // Check entries in db1
-        for (key, entry1) in &entries1 {
-            if let Some(entry2) = entries2.get(key) {
-                // Entry exists in both - check for differences
-                let username1 = entry1.get_username().map(|v| v.to_string()).unwrap_or_default();
-                let username2 = entry2.get_username().map(|v| v.to_string()).unwrap_or_default();
-
-                let pass1 = entry1.get_password().map(|v| v.to_string()).unwrap_or_default();
-                let pass2 = entry2.get_password().map(|v| v.to_string()).unwrap_or_default();
-
-                if username1 != username2 {
-                    differences.push(DifferenceInfo {
-                        title: entry1.get_title().unwrap_or("(no title)").to_string(),
-                        username: username1.clone(),
-                        diff_type: DifferenceType::UsernameDiffers {
-                            username1: username1,
-                            username2: username2,
-                        },
-                    });
-                } else if pass1 != pass2 {
-                    differences.push(DifferenceInfo {
-                        title: entry1.get_title().unwrap_or("(no title)").to_string(),
-                        username: username1,
-                        diff_type: DifferenceType::PasswordDiffers,
+            for (row, (name, _)) in names.into_iter().enumerate() {
+                let value1 = fields1.map(|fields| fields[row].1);
+                let value2 = fields2.map(|fields| fields[row].1);
+                let differs = value1 != value2;
+                let masked = name == "Password" && (hashed || !revealed_fields.contains(&index));
+
+                let text = |value: Option<&str>| -> egui::RichText {
+                    let shown = match value {
+                        None => "(missing)",
+                        Some("") => "(not set)",
+                        Some(_) if masked => "••••••••",
+                        Some(value) => value,
+                    };
+                    let text = egui::RichText::new(shown);
+                    if differs {
+                        text.color(egui::Color32::YELLOW)
+                    } else {
+                        text
+                    }
+                };
+                if name == "Password" && !hashed {
+                    ui.horizontal(|ui| {
+                        ui.label(name);
+                        reveal_toggle(ui, revealed_fields, index);
                     });
+                } else {
+                    ui.label(name);
                 }
-            } else {
-                // Entry only in db1
-                let title = entry1.get_title().unwrap_or("(no title)").to_string();
-                let username = entry1.get_username().map(|v| v.to_string()).unwrap_or_default();
-
-                differences.push(DifferenceInfo {
-                    title: title,
-                    username: username,
-                    diff_type: DifferenceType::OnlyInOne,
-                });
+                ui.label(text(value1));
+                ui.label(text(value2));
+                ui.end_row();
             }
-        }
-
-        // Check for entries only in db2
-        for (key, entry2) in &entries2 {
-            if !entries1.contains_key(key) {
-                let title = entry2.get_title().unwrap_or("(no title)").to_string();
-                let username = entry2.get_username().map(|v| v.to_string()).unwrap_or_default();
+        });
+}
 
-                differences.push(DifferenceInfo {
-                    title: title,
-                    username: username,
-                    diff_type: DifferenceType::OnlyInTwo,
-                });
-            }
+/// Shows or hides one difference's secret values; all of them hide again after `REVEAL_TIMEOUT`.
+fn reveal_toggle(ui: &mut egui::Ui, revealed_fields: &mut HashSet<usize>, index: usize) {
+    let mut revealed = revealed_fields.contains(&index);
+    if ui.toggle_value(&mut revealed, "👁 Reveal").changed() {
+        if revealed {
+            revealed_fields.insert(index);
+        } else {
+            revealed_fields.remove(&index);
         }
-
-        differences
     }
+}
 
-    fn collect_all_entries<'a>(&self, group: &'a Group) -> HashMap<String, &'a Entry> {
-        let mut entries = HashMap::new();
+fn drift_matrix_view(ui: &mut egui::Ui, drift: &DriftMatrix) {
+    let heading = if drift.rows.is_empty() {
+        format!("✔ All {} databases agree", drift.sources.len())
+    } else {
+        format!("Drift across {} databases: {} entries", drift.sources.len(), drift.rows.len())
+    };
+    egui::CollapsingHeader::new(heading).default_open(true).show(ui, |ui| {
+        egui::ScrollArea::vertical().id_salt("drift").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("drift_matrix").striped(true).show(ui, |ui| {
+                ui.strong("Entry");
+                for (index, source) in drift.sources.iter().enumerate() {
+                    ui.strong(format!("DB{}", index + 1)).on_hover_text(source);
+                }
+                ui.end_row();
 
-        for entry in group.entries() {
-            let title = entry.get_title().unwrap_or("(no title)");
-            entries.insert(String::from(title), entry);
-        }
+                for row in &drift.rows {
+                    if row.group_path.is_empty() {
+                        ui.label(&row.title);
+                    } else {
+                        ui.label(format!("{}/{}", row.group_path, row.title));
+                    }
+                    for state in &row.states {
+                        match state {
+                            DriftState::Matches => ui.colored_label(egui::Color32::LIGHT_GREEN, "✔"),
+                            DriftState::Differs => ui.colored_label(egui::Color32::YELLOW, "≠"),
+                            DriftState::Missing => ui.colored_label(egui::Color32::LIGHT_RED, "—"),
+                        };
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    });
+}
 
-        for child_group in group.groups() {
-            entries.extend(self.collect_all_entries(child_group));
+/// Lists a group's subgroups as collapsible headers and its entries below them, highlighting
+/// anything with differences. Clicking a highlighted entry stores its key in `selected`.
+fn explorer_tree(ui: &mut egui::Ui, side: usize, group: &ExplorerGroup, selected: &mut Option<String>) {
+    for child in &group.groups {
+        let mut name = egui::RichText::new(format!("📁 {}", child.name));
+        if child.has_differences {
+            name = name.color(egui::Color32::YELLOW);
+        }
+        egui::CollapsingHeader::new(name)
+            .id_salt(("explorer", side, child.uuid))
+            .show(ui, |ui| explorer_tree(ui, side, child, selected));
+    }
+    for entry in &group.entries {
+        match &entry.key {
+            Some(key) if entry.has_differences => {
+                let title = egui::RichText::new(format!("🔑 {}", entry.title)).color(egui::Color32::YELLOW);
+                let label = ui
+                    .add(egui::Label::new(title).sense(egui::Sense::click()))
+                    .on_hover_text("Show its differences");
+                if label.clicked() {
+                    *selected = Some(key.clone());
+                }
+            }
+            _ => {
+                ui.label(format!("🔑 {}", entry.title));
+            }
         }
-
-        entries
     }
 }
 
-enum DatabaseTarget {
-    First,
-    Second,
+fn recent_paths_menu(ui: &mut egui::Ui, recent_paths: &[String], path: &mut String) {
+    ui.add_enabled_ui(!recent_paths.is_empty(), |ui| {
+        ui.menu_button("🕘", |ui| {
+            for recent in recent_paths {
+                if ui.button(recent).clicked() {
+                    *path = recent.clone();
+                    ui.close();
+                }
+            }
+        })
+        .response
+        .on_hover_text("Recently used databases");
+    });
 }
 
 impl eframe::App for RustPassApp {
+    fn persist_egui_memory(&self) -> bool {
+        !self.in_memory
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        if self.in_memory {
+            return;
+        }
+        if self.settings_unreadable {
+            return;
+        }
+        self.remember_pair_profile();
+        let state = PersistedState {
+            database1_path: self.database1_path.clone(),
+            database2_path: self.database2_path.clone(),
+            recent_paths: self.recent_paths.clone(),
+            theme: self.theme,
+            palette: self.palette.clone(),
+            show_tree: self.show_tree,
+            show_timings: self.show_timings,
+            show_identical: self.show_identical,
+            anonymize_exports: self.anonymize_exports,
+            ignored_fields: self.ignored_fields.clone(),
+            pair_profiles: self.pair_profiles.clone(),
+            encrypt_settings: false,
+        };
+        if !self.encrypt_settings {
+            storage.set_string(ENCRYPTED_STATE_KEY, String::new());
+            eframe::set_value(storage, eframe::APP_KEY, &state);
+            return;
+        }
+        match encrypt_state(&state) {
+            Ok(sealed) => {
+                storage.set_string(ENCRYPTED_STATE_KEY, sealed);
+                let marker = PersistedState {
+                    encrypt_settings: true,
+                    ..Default::default()
+                };
+                eframe::set_value(storage, eframe::APP_KEY, &marker);
+            }
+            // Keep whatever was saved before rather than fall back to writing the settings in the clear
+            Err(e) => self.log(LogLevel::Error, format!("Could not encrypt the settings, so they were not saved: {}", e)),
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_sync(ctx);
+        self.poll_watch(ctx);
+        self.drop_stale_unlocked();
+        self.poll_reveal_timeout(ctx);
+        self.poll_clipboard_clears(ctx);
+        self.handle_dropped_files(ctx);
+        self.restore_pair_profile();
+        self.handle_shortcuts(ctx);
+        self.overwrite_dialog(ctx);
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::MenuBar::new().ui(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    let open_first = egui::Button::new("Open first database...")
+                        .shortcut_text(ctx.format_shortcut(&SHORTCUT_BROWSE_FIRST));
+                    if ui.add(open_first).clicked() {
+                        self.browse_file(DatabaseTarget::First);
+                        ui.close();
+                    }
+                    let open_second = egui::Button::new("Open second database...")
+                        .shortcut_text(ctx.format_shortcut(&SHORTCUT_BROWSE_SECOND));
+                    if ui.add_enabled(!self.snapshot_mode, open_second).clicked() {
+                        self.browse_file(DatabaseTarget::Second);
+                        ui.close();
+                    }
+                    ui.separator();
+                    ui.add_enabled_ui(!self.differences.is_empty() && !self.in_memory, |ui| {
+                        ui.menu_button("Export", |ui| {
+                            if ui.button("CSV...").clicked() {
+                                self.export_csv();
+                                ui.close();
+                            }
+                            if ui.button("JSON...").clicked() {
+                                self.export_json();
+                                ui.close();
+                            }
+                            if ui.button("HTML...").clicked() {
+                                self.export_html();
+                                ui.close();
+                            }
+                            ui.separator();
+                            ui.checkbox(&mut self.anonymize_exports, "Anonymize names").on_hover_text(
+                                "Replace titles, usernames and group names with placeholders such as \"entry_1a2b3c4d\", \
+                                 the same for the same entry throughout a report. URLs, notes and custom field \
+                                 values are kept as they are; passwords and protected fields are always redacted",
+                            );
+                        });
+                    });
+                    ui.separator();
+                    if ui.button("Import settings...").clicked() {
+                        self.import_settings();
+                        ui.close();
+                    }
+                    if ui
+                        .add_enabled(!self.in_memory, egui::Button::new("Export settings..."))
+                        .on_hover_text("Save the match strategy, compared fields and ignore lists as a JSON profile")
+                        .on_disabled_hover_text(IN_MEMORY_DISABLED)
+                        .clicked()
+                    {
+                        self.export_settings();
+                        ui.close();
+                    }
+                    ui.separator();
+                    if ui.button("Quit").clicked() {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+                ui.menu_button("Edit", |ui| {
+                    let clear = egui::Button::new("Clear").shortcut_text(ctx.format_shortcut(&SHORTCUT_CLEAR));
+                    if ui.add(clear).clicked() {
+                        self.clear_all();
+                        ui.close();
+                    }
+                    if ui.add_enabled(!self.is_syncing() && !self.snapshot_mode, egui::Button::new("Swap")).clicked() {
+                        self.swap_databases();
+                        ui.close();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    for theme in Theme::ALL {
+                        if ui.radio_value(&mut self.theme, theme, theme.label()).clicked() {
+                            ui.ctx().set_visuals(theme.visuals());
+                            ui.close();
+                        }
+                    }
+                    if ui.button("Difference colors...").clicked() {
+                        self.show_palette = true;
+                        ui.close();
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.show_tree, "Tree explorer")
+                        .on_hover_text("Browse both databases group by group after comparing");
+                    ui.checkbox(&mut self.show_timings, "Timings")
+                        .on_hover_text("Report how long decrypting and comparing took after each sync");
+                    ui.checkbox(&mut self.show_identical, "Identical entries")
+                        .on_hover_text("List the entries without differences, muted, after the differences");
+                    ui.separator();
+                    let encrypt = ui
+                        .add_enabled(
+                            !self.settings_unreadable && !self.in_memory,
+                            egui::Checkbox::new(&mut self.encrypt_settings, "Encrypt saved settings"),
+                        )
+                        .on_hover_text(
+                            "Encrypt recent paths and per-database settings with a key kept in the system keychain",
+                        );
+                    if encrypt.changed() && self.encrypt_settings {
+                        if let Err(e) = settings_key(true) {
+                            self.encrypt_settings = false;
+                            self.log(LogLevel::Error, format!("Could not store a settings key in the keychain: {}", e));
+                        }
+                    }
+                });
+                ui.menu_button("Help", |ui| {
+                    if ui.button("Keyboard shortcuts").clicked() {
+                        self.show_shortcuts = true;
+                        ui.close();
+                    }
+                    if ui.button("About RustPass").clicked() {
+                        self.show_about = true;
+                        ui.close();
+                    }
+                });
+                if self.in_memory {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(egui::RichText::new("🔒 In-memory mode").strong()).on_hover_text(
+                            "Started with --no-persist: settings, window state, saved passwords, exports, merged \
+                             databases and snapshots are not written",
+                        );
+                    });
+                }
+            });
+        });
+        self.help_windows(ctx);
+
+        egui::TopBottomPanel::bottom("log_panel").show(ctx, |ui| {
+            egui::CollapsingHeader::new(format!("Log ({})", self.log_entries.len())).show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(150.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for entry in &self.log_entries {
+                            ui.horizontal(|ui| {
+                                ui.weak(entry.at.format("%H:%M:%S").to_string());
+                                ui.colored_label(entry.level.color(ui), &entry.message);
+                            });
+                        }
+                    });
+            });
+        });
+
+        let mut back_to_differences = false;
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("RustPass - KeePass Database Sync");
             ui.separator();
@@ -222,78 +2671,910 @@ impl eframe::App for RustPassApp {
 
             ui.horizontal(|ui| {
                 ui.label("First Database Path:");
-                ui.text_edit_singleline(&mut self.database1_path);
-                if ui.button("Browse...").clicked() {
+                if ui.text_edit_singleline(&mut self.database1_path).has_focus() {
+                    self.focused_target = Some(DatabaseTarget::First);
+                }
+                let browse = ui.button("Browse...").on_hover_text(ctx.format_shortcut(&SHORTCUT_BROWSE_FIRST));
+                if browse.clicked() {
                     self.browse_file(DatabaseTarget::First);
                 }
+                recent_paths_menu(ui, &self.recent_paths, &mut self.database1_path);
+                path_hint(ui, &self.database1_path);
+                loaded_format_hint(ui, &self.loaded_formats[0], &self.database1_path);
             });
             ui.horizontal(|ui| {
-                ui.label("First Database Password:");
-                let password_field = egui::TextEdit::singleline(&mut self.database1_pass).password(true);
-                password_field.show(ui);
+                ui.label("First Database Unlock With:");
+                credential_selector(ui, "database1_credential", &mut self.database1_credential);
+                yubikey_selector(ui, &mut self.database1_yubikey_slot);
             });
-            ui.horizontal(|ui| {
-                ui.label("Second Database Path:");
-                ui.text_edit_singleline(&mut self.database2_path);
-                if ui.button("Browse...").clicked() {
-                    self.browse_file(DatabaseTarget::Second);
+            if self.database1_credential == CredentialType::KeyFileElement {
+                ui.horizontal(|ui| {
+                    ui.label("First Database Key File Element:");
+                    let element_field = egui::TextEdit::singleline(&mut *self.database1_key_element)
+                        .password(!self.show_pass1)
+                        .hint_text("hex or base64");
+                    element_field.show(ui);
+                    ui.toggle_value(&mut self.show_pass1, "👁").on_hover_text("Show key file element");
+                });
+            } else {
+                ui.horizontal(|ui| {
+                    ui.label("First Database Password:");
+                    let password_field = egui::TextEdit::singleline(&mut *self.database1_pass).password(!self.show_pass1);
+                    password_field.show(ui);
+                    ui.toggle_value(&mut self.show_pass1, "👁").on_hover_text("Show password");
+                    ui.add_enabled(!self.in_memory, egui::Checkbox::new(&mut self.remember_pass1, "Remember"))
+                        .on_hover_text("Save the password in the system keychain after it opens the database")
+                        .on_disabled_hover_text(IN_MEMORY_DISABLED);
+                    if ui
+                        .add_enabled(!self.database1_path.is_empty() && !self.in_memory, egui::Button::new("Forget"))
+                        .on_hover_text("Remove this database's password from the system keychain")
+                        .clicked()
+                    {
+                        self.forget_saved_password(DatabaseTarget::First);
+                    }
+                    let expected = self.database1_credential == CredentialType::Password && !self.database1_path.is_empty();
+                    password_hint(ui, &self.database1_pass, expected);
+                });
+            }
+            if self.database1_credential == CredentialType::KeyFile {
+                ui.horizontal(|ui| {
+                    ui.label("First Database Key File:");
+                    ui.text_edit_singleline(&mut self.database1_keyfile);
+                    if ui.button("Browse...").clicked() {
+                        self.browse_keyfile(DatabaseTarget::First);
+                    }
+                });
+            }
+            ui.add_enabled(!self.in_memory, egui::Checkbox::new(&mut self.snapshot_mode, "Compare against last snapshot"))
+                .on_hover_text(
+                    "Report what changed in the first database since the previous comparison. Only titles, \
+                     group names and hashes of the other fields and tags are kept, encrypted with the database's \
+                     own key",
+                )
+                .on_disabled_hover_text(IN_MEMORY_DISABLED);
+            if !self.snapshot_mode {
+                if ui
+                    .add_enabled(!self.is_syncing(), egui::Button::new("⇅ Swap"))
+                    .on_hover_text("Exchange the first and second database, including their keys")
+                    .clicked()
+                {
+                    self.swap_databases();
                 }
-            });
-            ui.horizontal(|ui| {
-                ui.label("Second Database Password:");
-                let password_field = egui::TextEdit::singleline(&mut self.database2_pass).password(true);
-                password_field.show(ui);
+                ui.horizontal(|ui| {
+                    ui.label("Second Database Path:");
+                    if ui.text_edit_singleline(&mut self.database2_path).has_focus() {
+                        self.focused_target = Some(DatabaseTarget::Second);
+                    }
+                    let browse = ui.button("Browse...").on_hover_text(ctx.format_shortcut(&SHORTCUT_BROWSE_SECOND));
+                    if browse.clicked() {
+                        self.browse_file(DatabaseTarget::Second);
+                    }
+                    recent_paths_menu(ui, &self.recent_paths, &mut self.database2_path);
+                    path_hint(ui, &self.database2_path);
+                    loaded_format_hint(ui, &self.loaded_formats[1], &self.database2_path);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Second Database Unlock With:");
+                    credential_selector(ui, "database2_credential", &mut self.database2_credential);
+                    yubikey_selector(ui, &mut self.database2_yubikey_slot);
+                });
+                if self.database2_credential == CredentialType::KeyFileElement {
+                    ui.horizontal(|ui| {
+                        ui.label("Second Database Key File Element:");
+                        let element_field = egui::TextEdit::singleline(&mut *self.database2_key_element)
+                            .password(!self.show_pass2)
+                            .hint_text("hex or base64");
+                        element_field.show(ui);
+                        ui.toggle_value(&mut self.show_pass2, "👁").on_hover_text("Show key file element");
+                    });
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label("Second Database Password:");
+                        if !self.same_password {
+                            let password_field =
+                                egui::TextEdit::singleline(&mut *self.database2_pass).password(!self.show_pass2);
+                            password_field.show(ui);
+                            ui.toggle_value(&mut self.show_pass2, "👁").on_hover_text("Show password");
+                        }
+                        ui.checkbox(&mut self.same_password, "Same as first").on_hover_text(
+                            "Unlock the second database with the first database's password, as for a backup of \
+                             the same vault. Untick to enter its own password again",
+                        );
+                        ui.add_enabled(!self.in_memory, egui::Checkbox::new(&mut self.remember_pass2, "Remember"))
+                            .on_hover_text("Save the password in the system keychain after it opens the database")
+                            .on_disabled_hover_text(IN_MEMORY_DISABLED);
+                        if ui
+                            .add_enabled(!self.database2_path.is_empty() && !self.in_memory, egui::Button::new("Forget"))
+                            .on_hover_text("Remove this database's password from the system keychain")
+                            .clicked()
+                        {
+                            self.forget_saved_password(DatabaseTarget::Second);
+                        }
+                        if !self.same_password {
+                            let expected =
+                                self.database2_credential == CredentialType::Password && !self.database2_path.is_empty();
+                            password_hint(ui, &self.database2_pass, expected);
+                        }
+                    });
+                }
+                if self.database2_credential == CredentialType::KeyFile {
+                    ui.horizontal(|ui| {
+                        ui.label("Second Database Key File:");
+                        ui.text_edit_singleline(&mut self.database2_keyfile);
+                        if ui.button("Browse...").clicked() {
+                            self.browse_keyfile(DatabaseTarget::Second);
+                        }
+                    });
+                }
+
+                let mut removed = None;
+                for (offset, source) in self.extra_sources.iter_mut().enumerate() {
+                    let number = offset + 3;
+                    ui.horizontal(|ui| {
+                        ui.label(format!("DB{} Path:", number));
+                        ui.text_edit_singleline(&mut source.path);
+                        if ui.button("Browse...").clicked() {
+                            if let Some(path) = pick_database_file() {
+                                source.path = path.display().to_string();
+                            }
+                        }
+                        recent_paths_menu(ui, &self.recent_paths, &mut source.path);
+                        if ui.button("➖ Remove").clicked() {
+                            removed = Some(offset);
+                        }
+                        path_hint(ui, &source.path);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(format!("DB{} Unlock With:", number));
+                        credential_selector(ui, ("extra_credential", offset), &mut source.credential);
+                        if source.credential == CredentialType::KeyFileElement {
+                            egui::TextEdit::singleline(&mut *source.key_element)
+                                .password(true)
+                                .hint_text("hex or base64")
+                                .show(ui);
+                        } else {
+                            ui.label("Password:");
+                            egui::TextEdit::singleline(&mut *source.password).password(true).show(ui);
+                        }
+                        if source.credential == CredentialType::KeyFile {
+                            ui.label("Key File:");
+                            ui.text_edit_singleline(&mut source.keyfile);
+                            if ui.button("Browse...").clicked() {
+                                if let Some(path) = rfd::FileDialog::new().pick_file() {
+                                    source.keyfile = path.display().to_string();
+                                }
+                            }
+                        }
+                        yubikey_selector(ui, &mut source.yubikey_slot);
+                    });
+                }
+                if let Some(offset) = removed {
+                    self.extra_sources.remove(offset);
+                }
+                let can_add = self.extra_sources.len() + 2 < MAX_DATABASES;
+                ui.add_enabled_ui(can_add, |ui| {
+                    if ui
+                        .button("➕ Add database")
+                        .on_hover_text("Further databases are compared against the first one in a drift overview")
+                        .clicked()
+                    {
+                        self.extra_sources.push(DatabaseSource::default());
+                    }
+                });
+            }
+
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Match entries by:");
+                egui::ComboBox::from_id_salt("match_strategy")
+                    .selected_text(self.match_strategy.label())
+                    .show_ui(ui, |ui| {
+                        for strategy in MatchStrategy::ALL {
+                            ui.selectable_value(&mut self.match_strategy, strategy, strategy.label());
+                        }
+                    });
+                if self.match_strategy == MatchStrategy::ByField {
+                    let selected = if self.match_field.is_empty() {
+                        String::from("(choose a field)")
+                    } else {
+                        self.match_field.clone()
+                    };
+                    ui.add_enabled_ui(!self.field_names.is_empty(), |ui| {
+                        egui::ComboBox::from_id_salt("match_field")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for name in &self.field_names {
+                                    ui.selectable_value(&mut self.match_field, name.clone(), name);
+                                }
+                            });
+                    })
+                    .response
+                    .on_disabled_hover_text("Compare once to list the fields of both databases");
+                }
+                ui.label("Limit to group:");
+                let groups = self.trees.as_ref().map(|[tree1, _]| tree1.group_paths()).unwrap_or_default();
+                let selected = if self.subtree.is_empty() {
+                    String::from("Whole database")
+                } else {
+                    self.subtree.join("/")
+                };
+                ui.add_enabled_ui(!groups.is_empty() || !self.subtree.is_empty(), |ui| {
+                    egui::ComboBox::from_id_salt("subtree")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.subtree, Vec::new(), "Whole database");
+                            for path in groups {
+                                let label = path.join("/");
+                                ui.selectable_value(&mut self.subtree, path, label);
+                            }
+                        });
+                })
+                .response
+                .on_disabled_hover_text("Compare once to list the first database's groups");
+                ui.checkbox(&mut self.include_recycle_bin, "Include Recycle Bin");
+                ui.checkbox(&mut self.compare_groups, "Compare groups");
+                ui.checkbox(&mut self.compare_history, "Compare history")
+                    .on_hover_text("Also report entries whose edit history diverged, even if current values match");
+                ui.checkbox(&mut self.compare_usage_metadata, "Compare usage metadata")
+                    .on_hover_text("Also report differing last access times and usage counts");
+                ui.checkbox(&mut self.ignore_trailing_whitespace, "Ignore trailing whitespace")
+                    .on_hover_text("Treat values that differ only in trailing spaces or CRLF/LF line endings as equal");
+                ui.checkbox(&mut self.strict_presence, "Strict field presence")
+                    .on_hover_text("Report a standard field, such as the password, missing from one entry but present and empty in the other");
+                ui.label("Ignore fields:");
+                egui::TextEdit::singleline(&mut self.ignored_fields)
+                    .hint_text("e.g. Device, Hostname")
+                    .desired_width(160.0)
+                    .show(ui)
+                    .response
+                    .on_hover_text("Comma-separated custom field names whose differences are not reported");
+                ui.label("Skip groups:");
+                egui::TextEdit::singleline(&mut self.excluded_groups)
+                    .hint_text("e.g. Templates*, Archive/Old")
+                    .desired_width(160.0)
+                    .show(ui)
+                    .response
+                    .on_hover_text(
+                        "Comma-separated group names to leave out with everything below them; * matches any text and \
+                         ? any character, and a name containing / is matched against the whole group path",
+                    );
             });
 
             ui.add_space(20.0);
 
             ui.horizontal(|ui| {
                 let button = egui::Button::new("🔄 Sync");
-                let button_enabled = !(self.database1_path.is_empty() || self.database1_pass.is_empty() || self.database2_path.is_empty() || self.database2_pass.is_empty());
-                if ui.add_enabled(button_enabled, button).clicked() {
+                let sync = ui
+                    .add_enabled(self.can_sync(), button)
+                    .on_hover_text(ctx.format_shortcut(&SHORTCUT_SYNC));
+                if sync.clicked() {
                     self.sync_databases();
                 }
+                if self.is_syncing() {
+                    ui.spinner();
+                    if ui
+                        .button("Cancel")
+                        .on_hover_text("Stop comparing; a database still being decrypted finishes in the background")
+                        .clicked()
+                    {
+                        self.cancel_sync();
+                    }
+                }
+                let mut watching = self.watcher.is_some();
+                let watch = ui
+                    .add_enabled(watching || self.can_sync(), egui::Checkbox::new(&mut watching, "Watch"))
+                    .on_hover_text("Compare again whenever either database file changes on disk");
+                if watch.changed() {
+                    self.toggle_watch(ctx);
+                }
+                if let Some(last_synced) = self.last_synced {
+                    ui.weak(format!("last synced at {}", last_synced.format("%H:%M:%S")));
+                }
+
+                let preview = ui
+                    .add_enabled(self.databases.is_some() && !self.is_syncing(), egui::Button::new("🔍 Preview merge"))
+                    .on_hover_text("List what saving would do with every entry, without writing anything");
+                if preview.clicked() {
+                    self.preview_merge();
+                }
+                let save_button = egui::Button::new("💾 Apply & Save...");
+                let save = ui
+                    .add_enabled(self.merge_preview.is_some() && !self.is_syncing() && !self.in_memory, save_button)
+                    .on_disabled_hover_text(if self.in_memory { IN_MEMORY_DISABLED } else { "Preview the merge first" });
+                if save.clicked() {
+                    self.save_merged_database();
+                }
+
+                let export = ui
+                    .add_enabled(!self.differences.is_empty() && !self.in_memory, egui::Button::new("📄 Export CSV..."))
+                    .on_disabled_hover_text(if self.in_memory { IN_MEMORY_DISABLED } else { "Compare the databases first" });
+                if export.clicked() {
+                    self.export_csv();
+                }
+
+                let export = ui
+                    .add_enabled(!self.differences.is_empty() && !self.in_memory, egui::Button::new("🧾 Export JSON..."))
+                    .on_disabled_hover_text(if self.in_memory { IN_MEMORY_DISABLED } else { "Compare the databases first" });
+                if export.clicked() {
+                    self.export_json();
+                }
+
+                let export = ui
+                    .add_enabled(!self.differences.is_empty() && !self.in_memory, egui::Button::new("🌐 Export HTML..."))
+                    .on_disabled_hover_text(if self.in_memory { IN_MEMORY_DISABLED } else { "Compare the databases first" });
+                if export.clicked() {
+                    self.export_html();
+                }
+
+                let copy_markdown = ui
+                    .add_enabled(!self.differences.is_empty(), egui::Button::new("📝 Copy as Markdown"))
+                    .on_hover_text("Copy a summary table without any field values, for tickets or chat");
+                if copy_markdown.clicked() {
+                    let markdown = differences_to_markdown(&self.differences);
+                    let description = format!("a summary of {} differences", self.differences.len());
+                    self.copy_to_clipboard(ctx, markdown, false, description);
+                }
+
+                let clear = ui.button("🧹 Clear").on_hover_text(format!(
+                    "Forget all paths, passwords and results; comparison settings and view filters stay ({})",
+                    ctx.format_shortcut(&SHORTCUT_CLEAR)
+                ));
+                if clear.clicked() {
+                    self.clear_all();
+                }
             });
 
             ui.add_space(20.0);
             ui.separator();
 
             ui.label(&self.status_message);
+            if self.locked_retry && ui.add_enabled(self.can_sync(), egui::Button::new("🔁 Retry")).clicked() {
+                self.sync_databases();
+            }
+            match self.sync_progress {
+                Some(SyncProgress::Comparing { done, total }) => {
+                    ui.add(egui::ProgressBar::new(done as f32 / total.max(1) as f32).show_percentage());
+                }
+                Some(SyncProgress::Downloading { received, total: Some(total), .. }) => {
+                    ui.add(egui::ProgressBar::new(received as f32 / total.max(1) as f32).show_percentage());
+                }
+                _ => {}
+            }
+
+            if let Some(drift) = &self.drift {
+                ui.add_space(10.0);
+                drift_matrix_view(ui, drift);
+            }
+
+            if let Some(trees) = self.trees.as_ref().filter(|_| self.show_tree) {
+                ui.add_space(10.0);
+                let mut selected = None;
+                egui::CollapsingHeader::new("🌳 Database trees").default_open(true).show(ui, |ui| {
+                    ui.columns(2, |columns| {
+                        for (side, (column, tree)) in columns.iter_mut().zip(trees).enumerate() {
+                            column.strong(&self.source_names[side]);
+                            egui::ScrollArea::vertical()
+                                .id_salt(("explorer", side))
+                                .max_height(250.0)
+                                .show(column, |ui| explorer_tree(ui, side, tree, &mut selected));
+                        }
+                    });
+                });
+                if selected.is_some() {
+                    self.scroll_to_key = selected;
+                }
+            }
 
-            // Display differences
-            if !self.differences.is_empty() {
+            if let Some(plan) = &self.merge_preview {
+                ui.add_space(20.0);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.heading("Merge Preview:");
+                    if ui.button("← Back to differences").clicked() {
+                        back_to_differences = true;
+                    }
+                });
+                let changes = plan.iter().filter(|planned| planned.action.changes_entry()).count();
+                ui.label(format!("{} of {} entries change; nothing is written until you apply and save", changes, plan.len()));
+                egui::ScrollArea::vertical().id_salt("merge_preview").show(ui, |ui| {
+                    for planned in plan {
+                        let (text, color) = describe_planned_action(planned, &self.source_names);
+                        ui.horizontal(|ui| {
+                            ui.colored_label(color, text);
+                            if !planned.group_path.is_empty() {
+                                ui.weak(format!("in {}", planned.group_path));
+                            }
+                        });
+                    }
+                });
+            } else if !self.differences.is_empty() {
+                // Display differences
                 ui.add_space(20.0);
                 ui.separator();
                 ui.heading("Differences Found:");
 
-                egui::ScrollArea::vertical().show(ui, |ui| {
+                if let Some(master) = self.authoritative {
+                    let copy = &self.source_names[master.other().index()];
+                    let mut modified = HashSet::new();
+                    let (mut missing, mut extra) = (0, 0);
                     for diff in &self.differences {
-                        ui.group(|ui| {
+                        match drift(diff.diff_type.kind(), master) {
+                            Drift::Missing => missing += 1,
+                            Drift::Extra => extra += 1,
+                            Drift::Modified => {
+                                modified.insert(&diff.key);
+                            }
+                        }
+                    }
+                    ui.horizontal_wrapped(|ui| {
+                        ui.strong(format!("Drift of {} from {}:", copy, self.source_names[master.index()]));
+                        ui.label(format!("{} missing, {} extra, {} modified", missing, extra, modified.len()));
+                    });
+                }
+                ui.horizontal_wrapped(|ui| {
+                    for (kind, count) in count_by_kind(&self.differences) {
+                        ui.colored_label(self.palette.color(kind), format!("{}: {}", kind.label(), count));
+                    }
+                    ui.weak(format!("{} entries identical", self.identical.len()));
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Filter:");
+                    ui.text_edit_singleline(&mut self.filter_text);
+                    ui.label("Sort by:");
+                    egui::ComboBox::from_id_salt("sort_order")
+                        .selected_text(self.sort_order.label())
+                        .show_ui(ui, |ui| {
+                            for order in SortOrder::ALL {
+                                ui.selectable_value(&mut self.sort_order, order, order.label());
+                            }
+                        });
+                    ui.checkbox(&mut self.conflicts_only, "Conflicts only")
+                        .on_hover_text("Hide entries and groups that exist in only one database");
+                    ui.label("Authoritative:");
+                    let name = |target: Option<DatabaseTarget>| match target {
+                        Some(target) => self.source_names[target.index()].clone(),
+                        None => String::from("Neither"),
+                    };
+                    let options = [None, Some(DatabaseTarget::First), Some(DatabaseTarget::Second)];
+                    let mut authoritative = self.authoritative;
+                    egui::ComboBox::from_id_salt("authoritative")
+                        .selected_text(name(authoritative))
+                        .show_ui(ui, |ui| {
+                            for option in options {
+                                ui.selectable_value(&mut authoritative, option, name(option));
+                            }
+                        })
+                        .response
+                        .on_hover_text("Read the other database as a copy of this one: what it is missing, has extra or changed");
+                    self.authoritative = authoritative;
+                });
+                ui.horizontal_wrapped(|ui| {
+                    for kind in DifferenceTypeKind::ALL {
+                        let mut shown = self.visible_kinds.contains(&kind);
+                        if ui.toggle_value(&mut shown, kind.label()).changed() {
+                            if shown {
+                                self.visible_kinds.insert(kind);
+                            } else {
+                                self.visible_kinds.remove(&kind);
+                            }
+                        }
+                    }
+                });
+
+                let visible = self.visible_differences();
+                if visible.len() != self.differences.len() {
+                    ui.label(format!("Showing {} of {}", visible.len(), self.differences.len()));
+                }
+
+                let mut copy_request = None;
+                let mut resolution_request = None;
+                let hashed = self.hashed_results;
+                if self.databases.is_some() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("Resolve all visible:");
+                        for option in Resolution::ALL {
+                            if ui.button(format!("→ {}", option.label())).clicked() {
+                                let mut keys: Vec<EntryId> = visible
+                                    .iter()
+                                    .map(|&index| &self.differences[index])
+                                    .filter(|diff| !diff.diff_type.kind().is_group())
+                                    .map(|diff| diff.key.clone())
+                                    .collect();
+                                keys.sort();
+                                keys.dedup();
+                                resolution_request = Some((keys, option));
+                            }
+                        }
+                    });
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let mut position = 0;
+                    while position < visible.len() {
+                        let index = visible[position];
+                        let first = &self.differences[index];
+
+                        // Custom field differences of the same entry, and setting differences of the
+                        // same group, share a single group
+                        let mut end = position + 1;
+                        let kind = first.diff_type.kind();
+                        if matches!(kind, DifferenceTypeKind::CustomFieldDiffers | DifferenceTypeKind::GroupMetadataDiffers) {
+                            while end < visible.len()
+                                && self.differences[visible[end]].key == first.key
+                                && self.differences[visible[end]].diff_type.kind() == kind
+                            {
+                                end += 1;
+                            }
+                        }
+
+                        let group = ui.group(|ui| {
                             ui.horizontal(|ui| {
-                                ui.strong(&diff.title);
-                            });
+                                ui.strong(&first.title);
+                                if !first.username.is_empty() {
+                                    ui.label(format!("({})", first.username));
+                                }
+
+                                if first.in_recycle_bin {
+                                    ui.colored_label(egui::Color32::GRAY, "🗑 Recycle Bin");
+                                }
+
+                                if first.expired {
+                                    ui.colored_label(egui::Color32::LIGHT_RED, "⌛ Expired");
+                                }
 
-                            match &diff.diff_type {
-                                DifferenceType::OnlyInOne => {
-                                    ui.colored_label(egui::Color32::YELLOW, "⚠ Only in Database 1");
+                                let badge = match first.freshness() {
+                                    Some(Freshness::FirstNewer) => Some((egui::Color32::LIGHT_GREEN, "DB1 is newer")),
+                                    Some(Freshness::SecondNewer) => Some((egui::Color32::LIGHT_GREEN, "DB2 is newer")),
+                                    Some(Freshness::Conflict) => Some((egui::Color32::LIGHT_RED, "⚡ Conflict")),
+                                    None => None,
+                                };
+                                if let Some(master) = self.authoritative {
+                                    if drift(first.diff_type.kind(), master) == Drift::Modified {
+                                        let copy = &self.source_names[master.other().index()];
+                                        ui.colored_label(egui::Color32::LIGHT_BLUE, format!("✏ Modified in {}", copy));
+                                    }
+                                }
+                                if let Some((color, text)) = badge {
+                                    let format_time = |time: Option<NaiveDateTime>| {
+                                        time.map(|t| t.to_string()).unwrap_or_else(|| String::from("unknown"))
+                                    };
+                                    ui.colored_label(color, text).on_hover_text(format!(
+                                        "DB1 modified: {}\nDB2 modified: {}",
+                                        format_time(first.modified1),
+                                        format_time(first.modified2)
+                                    ));
                                 }
-                                DifferenceType::OnlyInTwo => {
-                                    ui.colored_label(egui::Color32::YELLOW, "⚠ Only in Database 2");
+                            });
+
+                            if self.databases.is_some() && !first.diff_type.kind().is_group() {
+                                let mut resolution = self.resolution.get(&first.key).copied().unwrap_or_default();
+                                let mut changed = false;
+                                ui.horizontal(|ui| {
+                                    for option in Resolution::ALL {
+                                        changed |= ui.radio_value(&mut resolution, option, option.label()).changed();
+                                    }
+                                });
+                                if changed {
+                                    resolution_request = Some((vec![first.key.clone()], resolution));
                                 }
-                                DifferenceType::UsernameDiffers { username1, username2 } => {
-                                    ui.colored_label(egui::Color32::LIGHT_BLUE, "📧 Username differs:");
-                                    ui.label(format!("  DB1: {}", username1));
-                                    ui.label(format!("  DB2: {}", username2));
+                            }
+
+                            if first.snapshot1.is_some() || first.snapshot2.is_some() {
+                                egui::CollapsingHeader::new("🔍 Compare all fields")
+                                    .id_salt(("fields", &first.key, index))
+                                    .show(ui, |ui| {
+                                        field_table(ui, first, &mut self.revealed_fields, index, hashed);
+                                    });
+                            }
+
+                            for &diff_index in &visible[position..end] {
+                                let diff = &self.differences[diff_index];
+                                let color = self.palette.color(diff.diff_type.kind());
+                                match &diff.diff_type {
+                                    DifferenceType::OnlyInOne | DifferenceType::OnlyInTwo => {
+                                        ui.colored_label(color, one_sided_label(diff.diff_type.kind(), self.authoritative, &self.source_names));
+                                    }
+                                    DifferenceType::TitleDiffers { title1, title2 } => {
+                                        ui.colored_label(color, "✏ Title differs:");
+                                        ui.label(format!("  DB1: {}", display_title(Some(title1))));
+                                        ui.label(format!("  DB2: {}", display_title(Some(title2))));
+                                    }
+                                    DifferenceType::UsernameDiffers { username1, username2 } => {
+                                        ui.colored_label(color, "📧 Username differs:");
+                                        highlighted_values(ui, username1, username2, hashed);
+                                    }
+                                    DifferenceType::PasswordDiffers {
+                                        password1,
+                                        password2,
+                                        strength1,
+                                        strength2,
+                                    } => {
+                                        ui.horizontal(|ui| {
+                                            ui.colored_label(color, "🔑 Password differs");
+                                            // The strength of a hash says nothing about the password
+                                            if !hashed {
+                                                reveal_toggle(ui, &mut self.revealed_fields, diff_index);
+                                                for (label, strength) in [("DB1", *strength1), ("DB2", *strength2)] {
+                                                    let (name, color) = strength_label(strength);
+                                                    ui.colored_label(color, format!("{}: {}", label, name));
+                                                }
+                                            }
+                                        });
+                                        if self.revealed_fields.contains(&diff_index) {
+                                            ui.label(format!("  DB1: {}", password1.as_str()));
+                                            ui.label(format!("  DB2: {}", password2.as_str()));
+                                        }
+                                    }
+                                    DifferenceType::UrlDiffers { url1, url2 } => {
+                                        ui.colored_label(color, "🔗 URL differs:");
+                                        highlighted_values(ui, url1, url2, hashed);
+                                    }
+                                    DifferenceType::NotesDiffers { notes1, notes2 } => {
+                                        let (removed, added) = count_changed_lines(notes1, notes2);
+                                        ui.colored_label(
+                                            color,
+                                            format!("📝 Notes differ (-{} / +{} lines):", removed, added),
+                                        );
+                                        ui.label(format!("  DB1: {}", truncate_preview(notes1, NOTES_PREVIEW_CHARS)));
+                                        ui.label(format!("  DB2: {}", truncate_preview(notes2, NOTES_PREVIEW_CHARS)));
+                                    }
+                                    DifferenceType::TotpDiffers { otp1, otp2 } => {
+                                        ui.colored_label(color, "⏱ TOTP configuration differs");
+                                        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                                        for (label, otp) in [("DB1", otp1), ("DB2", otp2)] {
+                                            ui.horizontal(|ui| match otp.as_ref().map(|otp| otp.code_at(now)) {
+                                                None => {
+                                                    ui.label(format!("  {}: (no TOTP)", label));
+                                                }
+                                                Some(None) => {
+                                                    ui.label(format!("  {}: (invalid TOTP settings)", label));
+                                                }
+                                                Some(Some(code)) => {
+                                                    ui.label(format!("  {}:", label));
+                                                    ui.monospace(&code.code);
+                                                    countdown_ring(ui, code.remaining_secs as f32 / code.period_secs as f32);
+                                                    ui.label(format!("{}s", code.remaining_secs));
+                                                }
+                                            });
+                                        }
+                                        ui.ctx().request_repaint_after(Duration::from_secs(1));
+                                    }
+                                    DifferenceType::GroupOnlyInOne | DifferenceType::GroupOnlyInTwo => {
+                                        ui.colored_label(color, one_sided_label(diff.diff_type.kind(), self.authoritative, &self.source_names));
+                                    }
+                                    DifferenceType::EntryMoved { from_path, to_path } => {
+                                        let root_or = |path: &[String]| if path.is_empty() { String::from("(root)") } else { path.join("/") };
+                                        ui.colored_label(color, "🔀 Moved to another group:");
+                                        ui.label(format!("  DB1: {}", root_or(from_path)));
+                                        ui.label(format!("  DB2: {}", root_or(to_path)));
+                                    }
+                                    DifferenceType::ExpiryDiffers { expiry1, expiry2 } => {
+                                        ui.colored_label(color, "🕑 Expiry differs:");
+                                        ui.label(format!("  DB1: {}", expiry1));
+                                        ui.label(format!("  DB2: {}", expiry2));
+                                    }
+                                    DifferenceType::TagsDiffers { added, removed } => {
+                                        ui.colored_label(color, "🔖 Tags differ:");
+                                        ui.horizontal_wrapped(|ui| {
+                                            for tag in added {
+                                                ui.colored_label(egui::Color32::GREEN, format!("+{}", tag));
+                                            }
+                                            for tag in removed {
+                                                ui.colored_label(egui::Color32::RED, format!("-{}", tag));
+                                            }
+                                        });
+                                    }
+                                    DifferenceType::AttachmentsDiffer { added, removed, changed } => {
+                                        ui.colored_label(color, "📎 Attachments differ:");
+                                        for name in added {
+                                            ui.colored_label(egui::Color32::GREEN, format!("  + {} (only in DB2)", name));
+                                        }
+                                        for name in removed {
+                                            ui.colored_label(egui::Color32::RED, format!("  - {} (only in DB1)", name));
+                                        }
+                                        for name in changed {
+                                            ui.colored_label(egui::Color32::YELLOW, format!("  ~ {} (content differs)", name));
+                                        }
+                                    }
+                                    DifferenceType::HistoryDiffers { count1, count2, latest1, latest2 } => {
+                                        ui.colored_label(
+                                            color,
+                                            format!("📜 DB1 has {} history versions, DB2 has {}", count1, count2),
+                                        );
+                                        ui.label(format!("  DB1: {}", describe_history(*count1, *latest1)));
+                                        ui.label(format!("  DB2: {}", describe_history(*count2, *latest2)));
+                                    }
+                                    DifferenceType::IconDiffers { icon1, icon2 } => {
+                                        ui.colored_label(color, "🖼 Icon differs:");
+                                        ui.label(format!("  DB1: {}", icon1.describe()));
+                                        ui.label(format!("  DB2: {}", icon2.describe()));
+                                    }
+                                    DifferenceType::ProtectionDiffers { field, protected1 } => {
+                                        let protected_in = if *protected1 { 1 } else { 2 };
+                                        ui.colored_label(
+                                            color,
+                                            format!("🛡 Field \"{}\" is protected only in DB{}", field, protected_in),
+                                        );
+                                    }
+                                    DifferenceType::PresenceDiffers { field, present1 } => {
+                                        let (absent_in, empty_in) = if *present1 { (2, 1) } else { (1, 2) };
+                                        ui.colored_label(
+                                            color,
+                                            format!("∅ Field \"{}\" is absent in DB{} but empty in DB{}", field, absent_in, empty_in),
+                                        );
+                                    }
+                                    DifferenceType::AutoTypeDiffers { autotype1, autotype2 } => {
+                                        egui::CollapsingHeader::new(egui::RichText::new("⌨ Auto-type differs").color(color))
+                                            .id_salt(("auto_type", diff_index))
+                                            .show(ui, |ui| auto_type_details(ui, autotype1, autotype2));
+                                    }
+                                    DifferenceType::DanglingReference { field, reference, dangling_in_first } => {
+                                        let (dangling_in, resolved_in) = if *dangling_in_first { (1, 2) } else { (2, 1) };
+                                        ui.colored_label(
+                                            color,
+                                            format!(
+                                                "⚠ Field \"{}\" refers to an entry missing from DB{}; it only resolves in DB{}",
+                                                field, dangling_in, resolved_in
+                                            ),
+                                        );
+                                        ui.label(format!("  Reference: {}", reference));
+                                    }
+                                    DifferenceType::GroupMetadataDiffers { field, value1, value2 } => {
+                                        let describe = |value: &str| if value.is_empty() { String::from("(empty)") } else { truncate_preview(value, NOTES_PREVIEW_CHARS) };
+                                        ui.colored_label(color, format!("📂 Group {} differs:", field.to_lowercase()));
+                                        ui.label(format!("  DB1: {}", describe(value1)));
+                                        ui.label(format!("  DB2: {}", describe(value2)));
+                                    }
+                                    DifferenceType::MetadataDiffers { usage_count1, usage_count2, last_access1, last_access2 } => {
+                                        egui::CollapsingHeader::new(egui::RichText::new("📊 Usage metadata differs").color(color))
+                                            .id_salt(("usage_metadata", diff_index))
+                                            .show(ui, |ui| {
+                                                ui.label(format!("DB1: {}", describe_usage(*usage_count1, *last_access1)));
+                                                ui.label(format!("DB2: {}", describe_usage(*usage_count2, *last_access2)));
+                                            });
+                                    }
+                                    DifferenceType::CustomFieldDiffers { field, value1, value2, protected } => {
+                                        ui.horizontal(|ui| {
+                                            ui.colored_label(
+                                                color,
+                                                format!("🏷 Field \"{}\" differs:", field),
+                                            );
+                                            if *protected && !hashed {
+                                                reveal_toggle(ui, &mut self.revealed_fields, diff_index);
+                                            }
+                                        });
+                                        let masked = *protected && (hashed || !self.revealed_fields.contains(&diff_index));
+                                        for (label, value) in [("DB1", value1), ("DB2", value2)] {
+                                            let shown = if value.is_empty() {
+                                                "(not set)"
+                                            } else if masked {
+                                                "••••••••"
+                                            } else {
+                                                value.as_str()
+                                            };
+                                            ui.label(format!("  {}: {}", label, shown));
+                                        }
+                                    }
                                 }
-                                DifferenceType::PasswordDiffers => {
-                                    ui.colored_label(egui::Color32::RED, "🔑 Password differs");
+
+                                if let Some((value1, value2, secret)) = diff.diff_type.clipboard_values().filter(|_| !hashed) {
+                                    let (field, _, _) = diff.diff_type.export_values();
+                                    ui.horizontal(|ui| {
+                                        for (label, value) in [("DB1", value1), ("DB2", value2)] {
+                                            if ui.small_button(format!("📋 Copy {}", label)).clicked() {
+                                                let description = format!("{} of \"{}\" from {}", field, diff.title, label);
+                                                copy_request = Some((value.to_string(), secret, description));
+                                            }
+                                        }
+                                    });
                                 }
                             }
                         });
+                        if self.scroll_to_key.as_ref() == Some(&first.key) {
+                            group.response.scroll_to_me(Some(egui::Align::TOP));
+                        }
                         ui.add_space(5.0);
+                        position = end;
+                    }
+                    if self.show_identical {
+                        let filter = self.filter_text.to_lowercase();
+                        for entry in &self.identical {
+                            let text = if entry.group_path.is_empty() {
+                                entry.title.clone()
+                            } else {
+                                format!("{}/{}", entry.group_path, entry.title)
+                            };
+                            if !filter.is_empty()
+                                && !text.to_lowercase().contains(&filter)
+                                && !entry.username.to_lowercase().contains(&filter)
+                            {
+                                continue;
+                            }
+                            ui.horizontal(|ui| {
+                                ui.weak(format!("✔ {}", text));
+                                if !entry.username.is_empty() {
+                                    ui.weak(format!("({})", entry.username));
+                                }
+                            });
+                        }
+                    }
+                });
+                // Drop the request even if filters hide the entry, rather than jump there later
+                self.scroll_to_key = None;
+
+                if let Some((keys, resolution)) = resolution_request {
+                    self.set_resolution(keys, resolution);
+                }
+                if let Some((value, secret, description)) = copy_request {
+                    self.copy_to_clipboard(ui.ctx(), value, secret, description);
+                }
+            } else if self.has_compared {
+                ui.add_space(20.0);
+                egui::Frame::group(ui.style()).show(ui, |ui| {
+                    ui.colored_label(
+                        egui::Color32::LIGHT_GREEN,
+                        egui::RichText::new("✔ Databases are in sync").heading(),
+                    );
+                    if !self.identical.is_empty() {
+                        ui.weak(format!("{} entries identical", self.identical.len()));
                     }
                 });
             }
         });
+        if back_to_differences {
+            self.merge_preview = None;
+        }
+    }
+}
+
+fn describe_planned_action(planned: &PlannedAction, source_names: &[String; 2]) -> (String, egui::Color32) {
+    let title = &planned.title;
+    match planned.action {
+        MergeAction::Overwrite => (
+            format!("Overwrite {} with the version from {}", title, source_names[1]),
+            egui::Color32::GOLD,
+        ),
+        MergeAction::Add => (format!("Add {} from {}", title, source_names[1]), egui::Color32::LIGHT_GREEN),
+        MergeAction::Remove => (format!("Remove {}, found only in {}", title, source_names[0]), egui::Color32::RED),
+        MergeAction::KeepFirst => (format!("Keep {} as in {}", title, source_names[0]), egui::Color32::LIGHT_BLUE),
+        MergeAction::LeaveOut => (
+            format!("Leave out {}, found only in {}", title, source_names[1]),
+            egui::Color32::LIGHT_GRAY,
+        ),
+        MergeAction::Identical => (format!("Skip {} — identical", title), egui::Color32::GRAY),
+    }
+}
+
+/// The default sequences of both entries and every window association that is missing or different
+/// on one side.
+fn auto_type_details(ui: &mut egui::Ui, autotype1: &AutoTypeSettings, autotype2: &AutoTypeSettings) {
+    for (label, autotype) in [("DB1", autotype1), ("DB2", autotype2)] {
+        let sequence = if autotype.enabled {
+            AutoTypeSettings::describe_sequence(&autotype.sequence)
+        } else {
+            "disabled"
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!("{}:", label));
+            ui.monospace(sequence);
+        });
+    }
+    let windows: BTreeSet<&String> = autotype1.associations.keys().chain(autotype2.associations.keys()).collect();
+    for window in windows {
+        let (sequence1, sequence2) = (autotype1.associations.get(window), autotype2.associations.get(window));
+        if sequence1 == sequence2 {
+            continue;
+        }
+        fn describe(sequence: Option<&String>) -> &str {
+            sequence.map_or("(no association)", |sequence| AutoTypeSettings::describe_sequence(sequence))
+        }
+        ui.label(format!("Window \"{}\":", window));
+        ui.horizontal(|ui| {
+            ui.label("  DB1:");
+            ui.monospace(describe(sequence1));
+        });
+        ui.horizontal(|ui| {
+            ui.label("  DB2:");
+            ui.monospace(describe(sequence2));
+        });
     }
 }
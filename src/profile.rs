@@ -0,0 +1,200 @@
+//! Comparison profiles in JSON, so an administrator can hand the same match strategy, compared
+//! fields and ignore lists to every machine.
+
+use serde::{Deserialize, Serialize};
+
+use rustpass::{CompareOptions, MatchStrategy};
+
+/// The only profile layout so far; bumped when a field changes meaning.
+const PROFILE_VERSION: u32 = 1;
+
+/// Which entries are paired up, what is compared and what is left out. Fields missing from an
+/// imported file take their default, so a profile listing only ignored fields still applies fully.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SettingsProfile {
+    /// Required, so a file that is not a profile at all, such as exported differences, is refused
+    version: u32,
+    #[serde(default = "default_match_strategy")]
+    pub(crate) match_strategy: MatchStrategy,
+    #[serde(default)]
+    pub(crate) match_field: String,
+    #[serde(default)]
+    pub(crate) include_recycle_bin: bool,
+    #[serde(default = "default_compare_groups")]
+    pub(crate) compare_groups: bool,
+    #[serde(default)]
+    pub(crate) compare_history: bool,
+    #[serde(default)]
+    pub(crate) compare_usage_metadata: bool,
+    #[serde(default)]
+    pub(crate) ignore_trailing_whitespace: bool,
+    #[serde(default)]
+    pub(crate) strict_presence: bool,
+    /// Field names left out of the comparison
+    #[serde(default)]
+    pub(crate) ignored_fields: Vec<String>,
+    /// Group names or paths left out of the comparison, with `*` and `?` wildcards
+    #[serde(default)]
+    pub(crate) excluded_groups: Vec<String>,
+}
+
+fn default_match_strategy() -> MatchStrategy {
+    CompareOptions::default().match_strategy
+}
+
+fn default_compare_groups() -> bool {
+    CompareOptions::default().compare_groups
+}
+
+impl SettingsProfile {
+    /// The profile of `options`; the subtree is left out, as it names a group of one particular database.
+    pub(crate) fn of(options: &CompareOptions) -> Self {
+        SettingsProfile {
+            version: PROFILE_VERSION,
+            match_strategy: options.match_strategy,
+            match_field: options.match_field.clone(),
+            include_recycle_bin: options.include_recycle_bin,
+            compare_groups: options.compare_groups,
+            compare_history: options.compare_history,
+            compare_usage_metadata: options.compare_usage_metadata,
+            ignore_trailing_whitespace: options.ignore_trailing_whitespace,
+            strict_presence: options.strict_presence,
+            ignored_fields: options.ignored_fields.clone(),
+            excluded_groups: options.excluded_groups.clone(),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize settings: {}", e))
+    }
+
+    /// Reads and checks a whole profile, failing on the first problem so nothing is applied from a
+    /// malformed file.
+    pub(crate) fn from_json(json: &str) -> Result<Self, String> {
+        // serde would also read a struct from an array of its values, with a confusing message
+        if !json.trim_start().starts_with('{') {
+            return Err(String::from("Not a valid profile: expected a JSON object, as written by \"Export settings\""));
+        }
+        let profile: SettingsProfile = serde_json::from_str(json).map_err(|e| format!("Not a valid profile: {}", e))?;
+        if profile.version != PROFILE_VERSION {
+            return Err(format!(
+                "Unsupported profile version {}; this version of Rustpass reads version {}",
+                profile.version, PROFILE_VERSION
+            ));
+        }
+        if profile.match_strategy == MatchStrategy::ByField && profile.match_field.trim().is_empty() {
+            return Err(String::from("\"match_field\" must name a field when \"match_strategy\" is \"ByField\""));
+        }
+        for (list, names) in [("ignored_fields", &profile.ignored_fields), ("excluded_groups", &profile.excluded_groups)] {
+            for (index, name) in names.iter().enumerate() {
+                if name.trim().is_empty() {
+                    return Err(format!("\"{}\" item {} is empty", list, index + 1));
+                }
+                // The lists are edited as comma-separated text, where a comma would split the name
+                if name.contains(',') {
+                    return Err(format!("\"{}\" item {} (\"{}\") contains a comma", list, index + 1, name));
+                }
+            }
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RustPassApp;
+
+    fn rejection(json: &str) -> String {
+        match SettingsProfile::from_json(json) {
+            Ok(_) => panic!("the profile was accepted: {}", json),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn exported_profiles_read_back() {
+        let options = CompareOptions {
+            match_strategy: MatchStrategy::ByField,
+            match_field: String::from("ID"),
+            ignored_fields: vec![String::from("Notes")],
+            excluded_groups: vec![String::from("Archive/*")],
+            ..CompareOptions::default()
+        };
+        let json = SettingsProfile::of(&options).to_json().expect("the profile serializes");
+        let profile = SettingsProfile::from_json(&json).expect("the exported profile reads back");
+        assert!(profile.match_strategy == MatchStrategy::ByField);
+        assert_eq!(profile.match_field, "ID");
+        assert_eq!(profile.ignored_fields, ["Notes"]);
+        assert_eq!(profile.excluded_groups, ["Archive/*"]);
+    }
+
+    #[test]
+    fn missing_fields_take_their_default() {
+        let profile = SettingsProfile::from_json(r#"{"version": 1}"#).expect("a bare profile is accepted");
+        assert!(profile.match_strategy == CompareOptions::default().match_strategy);
+        assert_eq!(profile.compare_groups, CompareOptions::default().compare_groups);
+        assert!(profile.ignored_fields.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let e = rejection(r#"{"version": 1, "match_stratgy": "ByTitle"}"#);
+        assert!(e.starts_with("Not a valid profile: unknown field `match_stratgy`"), "{}", e);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let e = rejection(r#"{"version": 2}"#);
+        assert!(e.starts_with("Unsupported profile version 2"), "{}", e);
+        // Exported differences and other JSON lack the version altogether
+        let e = rejection(r#"{"match_strategy": "ByTitle"}"#);
+        assert!(e.starts_with("Not a valid profile: missing field `version`"), "{}", e);
+        assert!(rejection("[1]").starts_with("Not a valid profile: expected a JSON object"));
+    }
+
+    #[test]
+    fn empty_names_are_rejected() {
+        let e = rejection(r#"{"version": 1, "ignored_fields": ["Notes", " "]}"#);
+        assert_eq!(e, "\"ignored_fields\" item 2 is empty");
+        let e = rejection(r#"{"version": 1, "excluded_groups": [""]}"#);
+        assert_eq!(e, "\"excluded_groups\" item 1 is empty");
+    }
+
+    #[test]
+    fn names_with_a_comma_are_rejected() {
+        let e = rejection(r#"{"version": 1, "excluded_groups": ["Work, Home"]}"#);
+        assert_eq!(e, "\"excluded_groups\" item 1 (\"Work, Home\") contains a comma");
+    }
+
+    #[test]
+    fn matching_by_field_needs_a_field() {
+        let e = rejection(r#"{"version": 1, "match_strategy": "ByField", "match_field": "  "}"#);
+        assert!(e.starts_with("\"match_field\" must name a field"), "{}", e);
+        let json = r#"{"version": 1, "match_strategy": "ByField", "match_field": "ID"}"#;
+        assert!(SettingsProfile::from_json(json).is_ok());
+    }
+
+    #[test]
+    fn rejected_imports_leave_the_settings_untouched() {
+        let mut app = RustPassApp {
+            match_strategy: MatchStrategy::ByTitle,
+            compare_history: true,
+            ignored_fields: String::from("Notes"),
+            ..RustPassApp::default()
+        };
+        // Everything before the empty name is valid, and still none of it applies
+        let json = r#"{"version": 1, "match_strategy": "ByUuid", "compare_history": false, "ignored_fields": [""]}"#;
+        assert!(app.apply_settings_profile(json).is_err());
+        assert!(app.match_strategy == MatchStrategy::ByTitle);
+        assert!(app.compare_history);
+        assert_eq!(app.ignored_fields, "Notes");
+
+        let json = r#"{"version": 1, "ignored_fields": ["URL", "Notes"]}"#;
+        app.apply_settings_profile(json).expect("the profile applies");
+        assert!(app.match_strategy == CompareOptions::default().match_strategy);
+        assert!(!app.compare_history);
+        assert_eq!(app.ignored_fields, "URL, Notes");
+    }
+}
@@ -0,0 +1,301 @@
+//! Text reports of a comparison's differences. Values go through `DifferenceType::export_values`,
+//! so secrets are redacted in every format.
+
+use chrono::Local;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::difference::{count_by_kind, Difference, DifferenceType, DifferenceTypeKind};
+
+/// Bumped whenever the JSON report layout changes incompatibly.
+const JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    summary: JsonSummary,
+    differences: &'a [Difference],
+}
+
+#[derive(Serialize)]
+struct JsonSummary {
+    total: usize,
+    counts: BTreeMap<&'static str, usize>,
+}
+
+pub fn differences_to_json(differences: &[Difference]) -> Result<String, String> {
+    let counts = count_by_kind(differences)
+        .into_iter()
+        .map(|(kind, count)| (kind.name(), count))
+        .collect();
+
+    let report = JsonReport {
+        schema_version: JSON_SCHEMA_VERSION,
+        summary: JsonSummary {
+            total: differences.len(),
+            counts,
+        },
+        differences,
+    };
+    serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to serialize report: {}", e))
+}
+
+/// Hands out placeholders such as "entry_1a2b3c4d", the same for the same name throughout one report.
+/// Names are hashed with a random salt, so a placeholder cannot be traced back by hashing guesses.
+struct Placeholders {
+    salt: Uuid,
+}
+
+impl Placeholders {
+    fn of(&self, prefix: &str, name: &str) -> String {
+        if name.is_empty() {
+            return String::new();
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(prefix.as_bytes());
+        hasher.update(name.as_bytes());
+        let digest = hasher.finalize();
+        format!("{}_{}", prefix, digest[..4].iter().map(|b| format!("{:02x}", b)).collect::<String>())
+    }
+
+    fn path(&self, path: &str) -> String {
+        path.split('/').map(|name| self.of("group", name)).collect::<Vec<_>>().join("/")
+    }
+}
+
+/// Copies of `differences` fit for sharing: entry titles, usernames and group names are replaced by
+/// placeholders that stay the same for the same entry, user or group within the returned list. Which
+/// database each difference belongs to, its type and the remaining field values are kept.
+pub fn anonymize(differences: &[Difference]) -> Vec<Difference> {
+    let placeholders = Placeholders { salt: Uuid::new_v4() };
+    differences
+        .iter()
+        .map(|diff| {
+            let mut anonymized = diff.clone();
+            anonymized.title = if diff.diff_type.kind().is_group() {
+                placeholders.of("group", &diff.title)
+            } else {
+                // By key, so an entry whose title changed keeps one placeholder
+                placeholders.of("entry", &diff.key)
+            };
+            anonymized.username = placeholders.of("user", &diff.username);
+            anonymized.group_path = placeholders.path(&diff.group_path);
+            anonymized.snapshot1 = None;
+            anonymized.snapshot2 = None;
+            match &mut anonymized.diff_type {
+                DifferenceType::TitleDiffers { title1, title2 } => {
+                    *title1 = placeholders.of("title", title1);
+                    *title2 = placeholders.of("title", title2);
+                }
+                DifferenceType::UsernameDiffers { username1, username2 } => {
+                    *username1 = placeholders.of("user", username1);
+                    *username2 = placeholders.of("user", username2);
+                }
+                DifferenceType::EntryMoved { from_path, to_path } => {
+                    for name in from_path.iter_mut().chain(to_path.iter_mut()) {
+                        *name = placeholders.of("group", name);
+                    }
+                }
+                DifferenceType::GroupMetadataDiffers { field, value1, value2 } if field == "Name" => {
+                    *value1 = placeholders.of("group", value1);
+                    *value2 = placeholders.of("group", value2);
+                }
+                // The reference may search for an entry by its title or username
+                DifferenceType::DanglingReference { reference, .. } => {
+                    *reference = placeholders.of("reference", reference);
+                }
+                _ => {}
+            }
+            anonymized
+        })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn differences_to_csv(differences: &[Difference]) -> String {
+    let mut csv = String::from("title,group_path,username,diff_type,field,value1,value2\n");
+    for diff in differences {
+        let (field, value1, value2) = diff.diff_type.export_values();
+        let row = [
+            diff.title.as_str(),
+            diff.group_path.as_str(),
+            diff.username.as_str(),
+            diff.diff_type.kind().name(),
+            field,
+            &value1,
+            &value2,
+        ];
+        csv.push_str(&row.map(csv_field).join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// A Markdown summary for pasting into tickets or chat. Only names and difference types are
+/// included, never field values.
+pub fn differences_to_markdown(differences: &[Difference]) -> String {
+    // Pipes would end a table cell and newlines the row
+    let cell = |text: &str| text.replace('|', "\\|").replace(['\r', '\n'], " ");
+    let counts: Vec<String> = count_by_kind(differences)
+        .into_iter()
+        .map(|(kind, count)| format!("{}: {}", kind.label(), count))
+        .collect();
+
+    let mut markdown = format!("**{} differences** ({})\n\n", differences.len(), counts.join(", "));
+    markdown.push_str("| Title | Group | Type |\n|---|---|---|\n");
+    for diff in differences {
+        markdown.push_str(&format!(
+            "| {} | {} | {} |\n",
+            cell(&diff.title),
+            cell(&diff.group_path),
+            diff.diff_type.kind().label()
+        ));
+    }
+    markdown
+}
+
+fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; background: #1b1b1b; color: #dddddd; margin: 2em; }
+h1, h2 { font-weight: normal; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #444444; padding: 4px 8px; text-align: left; vertical-align: top; }
+th { background: #2a2a2a; }
+td.value { white-space: pre-wrap; font-family: monospace; }
+.kind { font-weight: bold; }";
+
+/// A standalone HTML report with a summary table and one section per difference type, each in the
+/// `[r, g, b]` color `color` gives it. Every value is escaped, and secrets are redacted just like in
+/// the other exports.
+pub fn differences_to_html(differences: &[Difference], color: impl Fn(DifferenceTypeKind) -> [u8; 3]) -> String {
+    let css_color = |kind: DifferenceTypeKind| {
+        let [r, g, b] = color(kind);
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    };
+    let counts = count_by_kind(differences);
+
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>RustPass report</title>\n");
+    html.push_str(&format!("<style>\n{}\n</style>\n</head>\n<body>\n", HTML_STYLE));
+    html.push_str("<h1>RustPass report</h1>\n");
+    html.push_str(&format!(
+        "<p>Generated {} &mdash; {} differences</p>\n",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        differences.len()
+    ));
+
+    html.push_str("<h2>Summary</h2>\n<table>\n<tr><th>Difference</th><th>Count</th></tr>\n");
+    for (kind, count) in &counts {
+        html.push_str(&format!(
+            "<tr><td class=\"kind\" style=\"color: {}\"><a href=\"#{}\" style=\"color: inherit\">{}</a></td><td>{}</td></tr>\n",
+            css_color(*kind),
+            kind.name(),
+            html_escape(kind.label()),
+            count
+        ));
+    }
+    html.push_str("</table>\n");
+
+    for kind in counts.keys() {
+        html.push_str(&format!(
+            "<h2 id=\"{}\" style=\"color: {}\">{}</h2>\n",
+            kind.name(),
+            css_color(*kind),
+            html_escape(kind.label())
+        ));
+        html.push_str("<table>\n<tr><th>Title</th><th>Group</th><th>Username</th><th>Field</th><th>DB1</th><th>DB2</th></tr>\n");
+        for diff in differences.iter().filter(|diff| diff.diff_type.kind() == *kind) {
+            let (field, value1, value2) = diff.diff_type.export_values();
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"value\">{}</td><td class=\"value\">{}</td></tr>\n",
+                html_escape(&diff.title),
+                html_escape(&diff.group_path),
+                html_escape(&diff.username),
+                html_escape(field),
+                html_escape(&value1),
+                html_escape(&value2)
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry_mut, DatabaseBuilder, EntryBuilder, GroupBuilder};
+    use crate::{compare, CompareOptions};
+    use keepass::db::Value;
+
+    #[test]
+    fn html_report_escapes_values_and_uses_the_given_colors() {
+        let db1 = DatabaseBuilder::new().build();
+        let mut db2 = db1.clone();
+        db2.root.add_child(EntryBuilder::new("<script>alert(1)</script>").username("O'Brien").build());
+        let html = differences_to_html(&compare(&db1, &db2, CompareOptions::default()), |_| [10, 20, 30]);
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("O&#39;Brien"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<h2 id=\"only_in_two\" style=\"color: #0a141e\">"));
+    }
+
+    #[test]
+    fn anonymized_entry_keeps_one_placeholder() {
+        let db1 = DatabaseBuilder::new()
+            .group(
+                GroupBuilder::new("Work")
+                    .entry(EntryBuilder::new("Mail").username("alice").url("https://mail.example"))
+                    .entry(EntryBuilder::new("VPN").username("alice").url("https://vpn.example")),
+            )
+            .build();
+        let mut db2 = db1.clone();
+        let mail = entry_mut(&mut db2, "Mail");
+        mail.fields.insert(String::from("URL"), Value::Unprotected(String::from("https://webmail.example")));
+        mail.fields.insert(String::from("Notes"), Value::Unprotected(String::from("Moved to webmail")));
+        entry_mut(&mut db2, "VPN").fields.insert(String::from("Notes"), Value::Unprotected(String::from("Retired")));
+
+        let differences = compare(&db1, &db2, CompareOptions::default());
+        assert_eq!(differences.len(), 3);
+        let anonymized = anonymize(&differences);
+        let (mail, vpn): (Vec<_>, Vec<_>) = anonymized.iter().partition(|diff| diff.key == differences[0].key);
+        let (mail, vpn) = (mail.as_slice(), vpn[0]);
+        assert_eq!(mail.len(), 2);
+
+        // Both differences of one entry share its placeholders, which give nothing away
+        assert_eq!((&mail[0].title, &mail[0].username), (&mail[1].title, &mail[1].username));
+        assert!(mail[0].title.starts_with("entry_") && mail[0].title != "Mail");
+        assert!(mail[0].username.starts_with("user_") && mail[0].username != "alice");
+        assert!(mail[0].group_path.starts_with("group_"));
+        // Another entry gets its own title placeholder, but the same user and group ones
+        assert_ne!(vpn.title, mail[0].title);
+        assert_eq!((&vpn.username, &vpn.group_path), (&mail[0].username, &mail[0].group_path));
+        // Values are kept, as the export menu says
+        assert!(anonymized.iter().any(|diff| matches!(&diff.diff_type, DifferenceType::UrlDiffers { url2, .. }
+            if url2 == "https://webmail.example")));
+    }
+}
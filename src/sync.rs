@@ -0,0 +1,1117 @@
+//! Opening databases and running the comparison engine over them, kept apart from the UI so it runs on
+//! a worker thread and from the command line alike.
+
+use base64::Engine;
+use chrono::{DateTime, Local};
+use keepass::config::{DatabaseConfig, DatabaseVersion, KdfConfig};
+use keepass::db::{Group, Node, Value};
+use keepass::error::{
+    CompressionConfigError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, InnerCipherConfigError,
+    KdfConfigError, OuterCipherConfigError,
+};
+use keepass::{ChallengeResponseKey, Database, DatabaseKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use rustpass::compare::{CompareOptions, DriftMatrix, ExplorerGroup, IdenticalEntry};
+use rustpass::fields::{decode_hex, normalize_sequence, tag_set};
+use rustpass::Difference;
+
+use crate::{DatabaseSource, WATCH_RETRY_DELAY};
+
+/// Everything the background worker needs to decrypt and compare both databases.
+pub(crate) struct SyncJob {
+    pub(crate) database1_path: String,
+    pub(crate) database1_pass: Zeroizing<String>,
+    pub(crate) database1_keyfile: String,
+    pub(crate) database1_key_element: Zeroizing<String>,
+    pub(crate) database1_yubikey_slot: Option<u8>,
+    pub(crate) database2_path: String,
+    pub(crate) database2_pass: Zeroizing<String>,
+    pub(crate) database2_keyfile: String,
+    pub(crate) database2_key_element: Zeroizing<String>,
+    pub(crate) database2_yubikey_slot: Option<u8>,
+    pub(crate) extra_sources: Vec<DatabaseSource>,
+    pub(crate) options: CompareOptions,
+    /// Tries at opening each database before giving up
+    pub(crate) open_attempts: u32,
+    /// Compare the first database against its last snapshot instead of against the second one
+    pub(crate) snapshot_mode: bool,
+    /// The first and second database once decrypted, possibly carried over from a failed attempt
+    pub(crate) unlocked: [Option<UnlockedDatabase>; 2],
+    /// Set from another thread to stop the job at its next checkpoint; decrypting a database can't be
+    /// interrupted, so that step always runs to its end
+    pub(crate) cancel: Arc<AtomicBool>,
+}
+
+/// The error a job stops with once `SyncJob::cancel` is set.
+pub(crate) const CANCELLED_MESSAGE: &str = "Comparison cancelled";
+
+/// A database that opened successfully, with the fingerprint of what it was opened from.
+pub(crate) struct UnlockedDatabase {
+    pub(crate) fingerprint: [u8; 32],
+    pub(crate) database: Database,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum SyncProgress {
+    OpeningFirst,
+    OpeningSecond,
+    OpeningSnapshot,
+    Opening(usize),
+    /// Bytes of a remote database received so far, out of the size the server announced
+    Downloading { index: usize, received: u64, total: Option<u64> },
+    Comparing { done: usize, total: usize },
+}
+
+impl SyncProgress {
+    pub(crate) fn label(&self) -> String {
+        match self {
+            SyncProgress::OpeningFirst => String::from("Opening DB1..."),
+            SyncProgress::OpeningSecond => String::from("Opening DB2..."),
+            SyncProgress::OpeningSnapshot => String::from("Opening the last snapshot..."),
+            SyncProgress::Opening(index) => format!("Opening DB{}...", index + 1),
+            SyncProgress::Downloading { index, received, total } => {
+                let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+                match total {
+                    Some(total) => format!(
+                        "Downloading DB{}... ({:.1} of {:.1} MB)",
+                        index + 1,
+                        megabytes(*received),
+                        megabytes(*total)
+                    ),
+                    None => format!("Downloading DB{}... ({:.1} MB)", index + 1, megabytes(*received)),
+                }
+            }
+            SyncProgress::Comparing { done, total } => format!("Comparing... ({} of {} entries)", done, total),
+        }
+    }
+}
+
+pub(crate) struct SyncOutcome {
+    pub(crate) differences: Vec<Difference>,
+    /// Entries found in both databases without any difference
+    pub(crate) identical: Vec<IdenticalEntry>,
+    pub(crate) drift: Option<DriftMatrix>,
+    /// Both databases and the options they were compared with, which merging reuses. Absent when
+    /// comparing against a snapshot, whose hashed values must never be merged back.
+    pub(crate) databases: Option<(Database, Database, CompareOptions)>,
+    pub(crate) entry_count1: usize,
+    pub(crate) entry_count2: usize,
+    pub(crate) snapshot: Option<SnapshotStatus>,
+    /// Both group hierarchies for the tree explorer
+    pub(crate) trees: [ExplorerGroup; 2],
+    /// Time spent opening the databases, including downloads
+    pub(crate) decrypt_time: Duration,
+    pub(crate) compare_time: Duration,
+    /// Every field name in either database, to pick a match field from
+    pub(crate) field_names: Vec<String>,
+    /// Entries matched by title because they lack the match field
+    pub(crate) key_fallbacks: usize,
+    /// File format of the database at the first and second path; the second is absent when
+    /// comparing against a snapshot
+    pub(crate) formats: [Option<String>; 2],
+    /// How the key derivation settings of each database fall short, laid out like `formats`
+    pub(crate) weak_kdfs: [Option<String>; 2],
+    /// Attachments in each KDBX 4 database's binary pool, laid out like `formats`. The keepass crate
+    /// drops the references linking them to entries, so none of them are compared.
+    pub(crate) uncompared_attachments: [usize; 2],
+}
+
+pub(crate) enum SnapshotStatus {
+    /// No earlier snapshot existed, so one was only taken
+    First,
+    /// Compared against the snapshot taken at this time
+    TakenAt(DateTime<Local>),
+}
+
+impl SyncJob {
+    /// Opens both databases and compares them. Databases that opened are left in `unlocked` when a
+    /// later step fails, so a retry only has to decrypt the one that did not.
+    pub(crate) fn run(&mut self, progress: &(dyn Fn(SyncProgress) + Sync)) -> Result<SyncOutcome, String> {
+        if self.snapshot_mode {
+            return self.run_snapshot(progress);
+        }
+
+        let started = Instant::now();
+        // Open and decrypt first database
+        progress(SyncProgress::OpeningFirst);
+        self.unlock(0, progress).map_err(|e| format!("Error opening first database: {}", e))?;
+        self.check_cancelled()?;
+
+        // Open and decrypt second database
+        progress(SyncProgress::OpeningSecond);
+        self.unlock(1, progress).map_err(|e| format!("Error opening second database: {}", e))?;
+        self.check_cancelled()?;
+        for (unlocked, name) in self.unlocked.iter().zip(["first", "second"]) {
+            if let Some(unlocked) = unlocked {
+                if !self.options.has_subtree(&unlocked.database) {
+                    return Err(format!("The {} database has no group {}", name, self.options.subtree.join("/")));
+                }
+            }
+        }
+
+        let mut extras = Vec::new();
+        for (offset, source) in self.extra_sources.iter().enumerate() {
+            let index = offset + 2;
+            progress(SyncProgress::Opening(index));
+            let db = self
+                .open_database(
+                    &source.path,
+                    &source.password,
+                    &source.keyfile,
+                    &source.key_element,
+                    source.yubikey_slot,
+                    &|received, total| progress(SyncProgress::Downloading { index, received, total }),
+                )
+                .map_err(|e| format!("Error opening DB{}: {}", index + 1, e))?;
+            extras.push(db);
+            self.check_cancelled()?;
+        }
+        let (Some(db1), Some(db2)) = (self.unlocked[0].take(), self.unlocked[1].take()) else {
+            unreachable!("both databases were just unlocked");
+        };
+        let (db1, db2) = (db1.database, db2.database);
+        let decrypt_time = started.elapsed();
+        let formats = [&db1, &db2].map(|db| Some(describe_format(&db.config.version)));
+        let weak_kdfs = [&db1, &db2].map(|db| weak_kdf(&db.config.kdf_config));
+        let uncompared_attachments = [&db1, &db2].map(|db| db.header_attachments.len());
+
+        // Compare databases
+        let started = Instant::now();
+        let drift = if extras.is_empty() {
+            None
+        } else {
+            let others: Vec<&Database> = std::iter::once(&db2).chain(&extras).collect();
+            let mut sources = vec![self.database1_path.clone(), self.database2_path.clone()];
+            sources.extend(self.extra_sources.iter().map(|source| source.path.clone()));
+            Some(self.options.drift_matrix(&db1, &others, sources, &self.cancel))
+        };
+        let differences = self.compare(&db1, &db2, progress);
+        self.check_cancelled()?;
+        let trees = self.options.explorer_trees(&db1, &db2, &differences);
+        Ok(SyncOutcome {
+            trees,
+            identical: self.options.identical_entries(&db1, &db2, &differences),
+            differences,
+            drift,
+            entry_count1: count_entries(&db1),
+            entry_count2: count_entries(&db2),
+            field_names: field_names(&[&db1, &db2]),
+            key_fallbacks: self.options.key_fallbacks(&db1) + self.options.key_fallbacks(&db2),
+            formats,
+            weak_kdfs,
+            uncompared_attachments,
+            databases: Some((db1, db2, self.options.clone())),
+            snapshot: None,
+            decrypt_time,
+            compare_time: started.elapsed(),
+        })
+    }
+
+    /// Compares the first database with the snapshot left by the previous run, then replaces that snapshot.
+    fn run_snapshot(&mut self, progress: &(dyn Fn(SyncProgress) + Sync)) -> Result<SyncOutcome, String> {
+        let started = Instant::now();
+        progress(SyncProgress::OpeningFirst);
+        self.unlock(0, progress).map_err(|e| format!("Error opening first database: {}", e))?;
+        self.check_cancelled()?;
+        let (current, format, weak, attachments) = match &self.unlocked[0] {
+            // Taken before hashing, which may convert the copy to KDBX 4
+            Some(unlocked) => (
+                hashed_snapshot(&unlocked.database),
+                describe_format(&unlocked.database.config.version),
+                weak_kdf(&unlocked.database.config.kdf_config),
+                unlocked.database.header_attachments.len(),
+            ),
+            None => unreachable!("the database was just unlocked"),
+        };
+
+        // The snapshot is sealed with the database's own key, so it opens with the same credentials
+        let path = snapshot_path(&self.database1_path)?;
+        let previous = match std::fs::metadata(&path) {
+            Ok(metadata) => {
+                progress(SyncProgress::OpeningSnapshot);
+                let snapshot = self
+                    .open_database(
+                        &path.display().to_string(),
+                        &self.database1_pass,
+                        &self.database1_keyfile,
+                        &self.database1_key_element,
+                        self.database1_yubikey_slot,
+                        &|_, _| {},
+                    )
+                    .map_err(|e| format!("Error opening the last snapshot (was the master key changed?): {}", e))?;
+                let taken_at = metadata.modified().map_or_else(|_| Local::now(), DateTime::<Local>::from);
+                Some((snapshot, taken_at))
+            }
+            Err(_) => None,
+        };
+        let decrypt_time = started.elapsed();
+
+        let started = Instant::now();
+        let outcome = match &previous {
+            Some((snapshot, taken_at)) => {
+                let differences = self.compare(snapshot, &current, progress);
+                SyncOutcome {
+                    trees: self.options.explorer_trees(snapshot, &current, &differences),
+                    identical: self.options.identical_entries(snapshot, &current, &differences),
+                    differences,
+                    drift: None,
+                    databases: None,
+                    entry_count1: count_entries(snapshot),
+                    entry_count2: count_entries(&current),
+                    field_names: field_names(&[&current]),
+                    key_fallbacks: self.options.key_fallbacks(snapshot) + self.options.key_fallbacks(&current),
+                    formats: [Some(format), None],
+                    weak_kdfs: [weak, None],
+                    uncompared_attachments: [attachments, 0],
+                    snapshot: Some(SnapshotStatus::TakenAt(*taken_at)),
+                    decrypt_time,
+                    compare_time: started.elapsed(),
+                }
+            }
+            None => SyncOutcome {
+                trees: self.options.explorer_trees(&current, &current, &[]),
+                differences: Vec::new(),
+                identical: Vec::new(),
+                drift: None,
+                databases: None,
+                entry_count1: 0,
+                entry_count2: count_entries(&current),
+                field_names: field_names(&[&current]),
+                key_fallbacks: self.options.key_fallbacks(&current),
+                formats: [Some(format), None],
+                weak_kdfs: [weak, None],
+                uncompared_attachments: [attachments, 0],
+                snapshot: Some(SnapshotStatus::First),
+                decrypt_time,
+                compare_time: started.elapsed(),
+            },
+        };
+
+        // Keep the previous snapshot when cancelled, so the next run still reports these changes
+        self.check_cancelled()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create the snapshot folder: {}", e))?;
+        }
+        self.save_database(&current, &path, false)
+            .map_err(|e| format!("Error saving the new snapshot: {}", e))?;
+        self.unlocked[0] = None;
+        Ok(outcome)
+    }
+
+    fn compare(&self, db1: &Database, db2: &Database, progress: &(dyn Fn(SyncProgress) + Sync)) -> Vec<Difference> {
+        self.options
+            .compare_databases(db1, db2, &self.cancel, &|done, total| progress(SyncProgress::Comparing { done, total }))
+    }
+
+    fn check_cancelled(&self) -> Result<(), String> {
+        if self.cancel.load(AtomicOrdering::Relaxed) {
+            Err(String::from(CANCELLED_MESSAGE))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Path and credentials of the first (0) or second (1) database.
+    fn slot(&self, slot: usize) -> (&str, &str, &str, &str, Option<u8>) {
+        if slot == 0 {
+            (
+                &self.database1_path,
+                &self.database1_pass,
+                &self.database1_keyfile,
+                &self.database1_key_element,
+                self.database1_yubikey_slot,
+            )
+        } else {
+            (
+                &self.database2_path,
+                &self.database2_pass,
+                &self.database2_keyfile,
+                &self.database2_key_element,
+                self.database2_yubikey_slot,
+            )
+        }
+    }
+
+    /// Identifies what a slot's database would be opened from: its path, credentials and the file's
+    /// modification time, so an unlocked copy is only reused while none of them has changed.
+    pub(crate) fn unlock_fingerprint(&self, slot: usize) -> [u8; 32] {
+        let (path, password, keyfile, key_element, yubikey_slot) = self.slot(slot);
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut hasher = Sha256::new();
+        for part in [path, password, keyfile, key_element] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(format!("{:?} {:?}", yubikey_slot, modified));
+        hasher.finalize().into()
+    }
+
+    fn unlock(&mut self, slot: usize, progress: &(dyn Fn(SyncProgress) + Sync)) -> Result<(), String> {
+        if self.unlocked[slot].is_some() {
+            return Ok(());
+        }
+        // Taken before reading, so a write during a slow decryption makes the copy stale
+        let fingerprint = self.unlock_fingerprint(slot);
+        let (path, password, keyfile, key_element, yubikey_slot) = self.slot(slot);
+        let on_download = |received, total| progress(SyncProgress::Downloading { index: slot, received, total });
+        let database = self.open_database(path, password, keyfile, key_element, yubikey_slot, &on_download)?;
+        self.unlocked[slot] = Some(UnlockedDatabase { fingerprint, database });
+        Ok(())
+    }
+
+    fn open_database(
+        &self,
+        path: &str,
+        password: &str,
+        keyfile: &str,
+        key_element: &str,
+        yubikey_slot: Option<u8>,
+        on_download: &dyn Fn(u64, Option<u64>),
+    ) -> Result<Database, String> {
+        let mut attempts_left = self.open_attempts;
+        loop {
+            match self.try_open_database(path, password, keyfile, key_element, yubikey_slot, on_download) {
+                Ok(database) => return Ok(database),
+                // A file caught halfway through being saved usually reads fine a moment later
+                Err(failure) if failure.transient && attempts_left > 1 && !self.cancel.load(AtomicOrdering::Relaxed) => {
+                    attempts_left -= 1;
+                    thread::sleep(WATCH_RETRY_DELAY);
+                }
+                Err(failure) => return Err(failure.message),
+            }
+        }
+    }
+
+    fn try_open_database(
+        &self,
+        path: &str,
+        password: &str,
+        keyfile: &str,
+        key_element: &str,
+        yubikey_slot: Option<u8>,
+        on_download: &dyn Fn(u64, Option<u64>),
+    ) -> Result<Database, OpenFailure> {
+        let data = if is_remote_path(path) {
+            download_database(path, &self.cancel, on_download).map_err(OpenFailure::permanent)?
+        } else {
+            std::fs::read(path).map_err(|e| {
+                OpenFailure::transient(if is_locked_error(&e) {
+                    String::from(LOCKED_FILE_MESSAGE)
+                } else {
+                    format!("Failed to open file: {}", e)
+                })
+            })?
+        };
+
+        check_signature(&data)?;
+        let key = database_key(password, keyfile, key_element, yubikey_slot).map_err(OpenFailure::permanent)?;
+        parse_database(&data, key)
+    }
+
+    /// Writes `db` to a temporary file next to `path` and renames it into place, so a crash mid-write
+    /// never leaves a truncated database behind. Returns where the previous file was backed up, if asked to.
+    pub(crate) fn save_database(&self, db: &Database, path: &Path, backup: bool) -> Result<Option<PathBuf>, String> {
+        let key = database_key(
+            &self.database1_pass,
+            &self.database1_keyfile,
+            &self.database1_key_element,
+            self.database1_yubikey_slot,
+        )?;
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| String::from("Not a file path"))?
+            .to_string_lossy()
+            .into_owned();
+        let temp_path = path.with_file_name(format!("{}.tmp", file_name));
+
+        let written = File::create(&temp_path)
+            .map_err(|e| format!("Failed to create file: {}", e))
+            .and_then(|mut file| {
+                db.save(&mut file, key)
+                    .map_err(|e| format!("Failed to write database: {}", e))?;
+                file.sync_all().map_err(|e| format!("Failed to write database: {}", e))
+            });
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        let backup_path = if backup {
+            let backup_path = path.with_file_name(format!("{}.{}.bak", file_name, Local::now().format("%Y%m%d-%H%M%S")));
+            if let Err(e) = std::fs::copy(path, &backup_path) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(format!("Failed to back up the existing file: {}", e));
+            }
+            Some(backup_path)
+        } else {
+            None
+        };
+
+        std::fs::rename(&temp_path, path).map_err(|e| {
+            let _ = std::fs::remove_file(&temp_path);
+            format!("Failed to replace {}: {}", path.display(), e)
+        })?;
+        Ok(backup_path)
+    }
+}
+
+/// The file format as KeePass clients name it, such as "KDBX 4.0".
+pub(crate) fn describe_format(version: &DatabaseVersion) -> String {
+    match version {
+        DatabaseVersion::KDB(_) => String::from("KeePass 1.x (KDB)"),
+        DatabaseVersion::KDB2(minor) => format!("KDBX 2.{}", minor),
+        DatabaseVersion::KDB3(minor) => format!("KDBX 3.{}", minor),
+        DatabaseVersion::KDB4(minor) => format!("KDBX 4.{}", minor),
+    }
+}
+
+/// AES-KDF rounds below which a database counts as weakly protected, the KeePass 2 default for KDBX 3.1.
+const MIN_AES_ROUNDS: u64 = 60_000;
+/// Argon2 memory and iterations below which a database counts as weakly protected.
+const MIN_ARGON2_MEMORY: u64 = 16 * 1024 * 1024;
+const MIN_ARGON2_ITERATIONS: u64 = 2;
+
+/// How `kdf` falls short of the recommended strength, such as "AES-KDF with only 6000 rounds", if it does.
+pub(crate) fn weak_kdf(kdf: &KdfConfig) -> Option<String> {
+    let (name, iterations, memory) = match kdf {
+        KdfConfig::Aes { rounds } => {
+            return (*rounds < MIN_AES_ROUNDS).then(|| format!("AES-KDF with only {} rounds", rounds));
+        }
+        KdfConfig::Argon2 { iterations, memory, .. } => ("Argon2d", *iterations, *memory),
+        KdfConfig::Argon2id { iterations, memory, .. } => ("Argon2id", *iterations, *memory),
+    };
+    if memory < MIN_ARGON2_MEMORY {
+        Some(format!("{} with only {} KiB of memory", name, memory / 1024))
+    } else if iterations < MIN_ARGON2_ITERATIONS {
+        Some(format!("{} with only {} iteration", name, iterations))
+    } else {
+        None
+    }
+}
+
+fn database_key(
+    password: &str,
+    keyfile: &str,
+    key_element: &str,
+    yubikey_slot: Option<u8>,
+) -> Result<DatabaseKey, String> {
+    let mut key = DatabaseKey::new();
+    if !password.is_empty() {
+        key = key.with_password(password);
+    }
+    if !keyfile.is_empty() {
+        let mut keyfile = File::open(keyfile).map_err(|e| format!("Failed to read key file: {}", e))?;
+        key = key
+            .with_keyfile(&mut keyfile)
+            .map_err(|e| format!("Failed to read key file: {}", e))?;
+    }
+    if !key_element.is_empty() {
+        // Read as the contents of a 32-byte key file, which keepass takes as the key-file element as is
+        let bytes = decode_key_element(key_element)?;
+        key = key
+            .with_keyfile(&mut bytes.as_slice())
+            .map_err(|e| format!("Failed to use key file element: {}", e))?;
+    }
+    if let Some(slot) = yubikey_slot {
+        let yubikeys = ChallengeResponseKey::get_available_yubikeys()
+            .map_err(|e| format!("Failed to search for a YubiKey: {}", e))?;
+        // With several keys plugged in, the first one found answers the challenge
+        let Some(yubikey) = yubikeys.into_iter().next() else {
+            return Err(String::from("YubiKey not detected; insert it and try again"));
+        };
+        key = key.with_challenge_response_key(ChallengeResponseKey::YubikeyChallenge(yubikey, slot.to_string()));
+    }
+    Ok(key)
+}
+
+/// Decodes a pasted key file element, trying hex first since every hex string is also valid base64.
+fn decode_key_element(key_element: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let compact = Zeroizing::new(key_element.chars().filter(|c| !c.is_whitespace()).collect::<String>());
+    let bytes = decode_hex(&compact)
+        .or_else(|| base64::engine::general_purpose::STANDARD.decode(compact.as_bytes()).ok())
+        .ok_or_else(|| String::from("Key file element is neither valid hex nor base64"))?;
+    let bytes = Zeroizing::new(bytes);
+    if bytes.len() != 32 {
+        return Err(format!("Key file element must be 32 bytes, but it decodes to {} bytes", bytes.len()));
+    }
+    Ok(bytes)
+}
+
+/// Why a database failed to open, and whether trying again shortly may succeed.
+struct OpenFailure {
+    message: String,
+    /// Set for read errors and damaged data, which are also what a file still being written looks like
+    transient: bool,
+}
+
+impl OpenFailure {
+    fn permanent(message: String) -> Self {
+        OpenFailure { message, transient: false }
+    }
+
+    fn transient(message: String) -> Self {
+        OpenFailure { message, transient: true }
+    }
+}
+
+/// Detects the format from the file signature rather than the extension, which is often wrong for
+/// old exports, and refuses the formats that can't be read.
+fn check_signature(data: &[u8]) -> Result<DatabaseVersion, OpenFailure> {
+    match DatabaseVersion::parse(data) {
+        Ok(version @ (DatabaseVersion::KDB(_) | DatabaseVersion::KDB3(_) | DatabaseVersion::KDB4(_))) => Ok(version),
+        Ok(DatabaseVersion::KDB2(_)) => Err(OpenFailure::permanent(String::from(
+            "This is a pre-release KeePass 2 database, which is not supported; open and save it in KeePass 2.x to upgrade it",
+        ))),
+        // A file that is still being written may be empty for a moment
+        Err(_) => Err(OpenFailure::transient(String::from(
+            "File is not a KeePass database (unrecognized file signature)",
+        ))),
+    }
+}
+
+/// Decrypts a database read into memory.
+fn parse_database(data: &[u8], key: DatabaseKey) -> Result<Database, OpenFailure> {
+    // keepass panics on some truncated KDBX 4 files instead of returning an error
+    let parsed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| Database::parse(data, key)));
+    match parsed {
+        Ok(parsed) => parsed.map_err(|e| OpenFailure {
+            message: describe_open_error(&e),
+            transient: is_partial_write(&e),
+        }),
+        Err(_) => Err(OpenFailure::transient(String::from(
+            "File appears truncated; it may still be being written",
+        ))),
+    }
+}
+
+/// Whether `error` may come from reading a file halfway through being saved, rather than from a
+/// wrong key or a setting that will never be readable.
+fn is_partial_write(error: &DatabaseOpenError) -> bool {
+    match error {
+        DatabaseOpenError::Io(_) => true,
+        DatabaseOpenError::DatabaseIntegrity(e) => unsupported_algorithm(e).is_none(),
+        DatabaseOpenError::Key(_) | DatabaseOpenError::UnsupportedVersion => false,
+    }
+}
+
+fn describe_open_error(error: &DatabaseOpenError) -> String {
+    match error {
+        DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey) => String::from("Incorrect password or key file"),
+        DatabaseOpenError::Key(DatabaseKeyError::ChallengeResponseKeyError(e)) => {
+            format!("YubiKey challenge-response failed: {}", e)
+        }
+        DatabaseOpenError::Key(e) => format!("Failed to build database key: {}", e),
+        DatabaseOpenError::Io(e) => format!("Failed to read database: {}", e),
+        DatabaseOpenError::DatabaseIntegrity(e) => match unsupported_algorithm(e) {
+            Some(algorithm) => format!(
+                "Database uses {} which isn't supported; this is a limitation of RustPass, not a wrong password",
+                algorithm
+            ),
+            None => format!("File appears corrupt or is not a KeePass database ({})", e),
+        },
+        DatabaseOpenError::UnsupportedVersion => String::from("This database version is not supported"),
+    }
+}
+
+/// Names the algorithm behind errors raised for header settings the keepass crate can't handle.
+fn unsupported_algorithm(error: &DatabaseIntegrityError) -> Option<String> {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let algorithm = match error {
+        DatabaseIntegrityError::OuterCipher(OuterCipherConfigError::InvalidOuterCipherID { cid }) => {
+            match hex(cid).as_str() {
+                "61ab05a1946441c38d743a563df8dd35" => String::from("AES-128"),
+                id => format!("an unknown cipher ({})", id),
+            }
+        }
+        DatabaseIntegrityError::InnerCipher(InnerCipherConfigError::InvalidInnerCipherID { cid }) => match cid {
+            1 => String::from("ArcFour to protect passwords"),
+            _ => format!("an unknown password protection cipher ({})", cid),
+        },
+        DatabaseIntegrityError::KdfSettings(KdfConfigError::InvalidKDFUUID { uuid }) => {
+            format!("an unknown key derivation function ({})", hex(uuid))
+        }
+        DatabaseIntegrityError::KdfSettings(KdfConfigError::InvalidKDFVersion { version }) => {
+            format!("Argon2 version {:#x}", version)
+        }
+        DatabaseIntegrityError::Compression(CompressionConfigError::InvalidCompressionSuite { cid }) => {
+            format!("an unknown compression algorithm ({})", cid)
+        }
+        // KeePass 1.x flags: 2 is Rijndael and 8 is Twofish, the two ciphers that can be read
+        DatabaseIntegrityError::InvalidFixedCipherID { cid } if cid & 4 != 0 => String::from("ArcFour"),
+        DatabaseIntegrityError::InvalidFixedCipherID { cid } => format!("an unknown cipher (flags {:#x})", cid),
+        _ => return None,
+    };
+    Some(algorithm)
+}
+
+/// Shown when another program, typically the password manager itself, has the file locked.
+pub(crate) const LOCKED_FILE_MESSAGE: &str = "Database is open in another application — close it and retry";
+
+/// Whether reading failed because another process holds a lock on the file.
+fn is_locked_error(error: &std::io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    if cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33)) {
+        return true;
+    }
+    matches!(error.kind(), std::io::ErrorKind::ResourceBusy | std::io::ErrorKind::WouldBlock)
+}
+
+/// How long downloading a remote database may take before giving up.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Remote databases larger than this are refused, so a wrong URL can't exhaust the memory.
+const MAX_DOWNLOAD_BYTES: u64 = 500_000_000;
+
+/// Whether a database path is an http(s) URL to download rather than a local file.
+pub(crate) fn is_remote_path(path: &str) -> bool {
+    let lower = path.get(..8).unwrap_or(path).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Downloads a database into memory; it is never written to disk. Errors name the network as the
+/// cause so they can't be mistaken for a wrong password.
+fn download_database(url: &str, cancel: &AtomicBool, on_progress: &dyn Fn(u64, Option<u64>)) -> Result<Vec<u8>, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(DOWNLOAD_TIMEOUT))
+        .build()
+        .into();
+    let response = agent.get(url).call().map_err(|e| match e {
+        ureq::Error::StatusCode(code) => format!("Network error: the server answered HTTP {}", code),
+        ureq::Error::Timeout(_) => format!("Network error: no answer within {} seconds", DOWNLOAD_TIMEOUT.as_secs()),
+        e => format!("Network error: {}", e),
+    })?;
+    let total = response
+        .headers()
+        .get("content-length")
+        .and_then(|length| length.to_str().ok()?.parse().ok());
+    read_download(response.into_body().into_reader(), total, MAX_DOWNLOAD_BYTES, cancel, on_progress)
+}
+
+/// Reads a download body the server announced as `total` bytes, refusing it as soon as it is known
+/// to exceed `limit`, whether from the announced size or from what arrived.
+fn read_download(
+    reader: impl std::io::Read,
+    total: Option<u64>,
+    limit: u64,
+    cancel: &AtomicBool,
+    on_progress: &dyn Fn(u64, Option<u64>),
+) -> Result<Vec<u8>, String> {
+    let too_large = || format!("Download too large: remote databases over {} MB are refused", limit / 1_000_000);
+    if total.is_some_and(|total| total > limit) {
+        return Err(too_large());
+    }
+    // One byte past the limit tells a body of exactly `limit` bytes from a longer one
+    let mut reader = std::io::Read::take(reader, limit + 1);
+    let mut data = Vec::new();
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        if cancel.load(AtomicOrdering::Relaxed) {
+            return Err(String::from(CANCELLED_MESSAGE));
+        }
+        match std::io::Read::read(&mut reader, &mut chunk) {
+            Ok(0) => return Ok(data),
+            Ok(read) => {
+                data.extend_from_slice(&chunk[..read]);
+                if data.len() as u64 > limit {
+                    return Err(too_large());
+                }
+                on_progress(data.len() as u64, total);
+            }
+            Err(e) => return Err(format!("Network error while downloading: {}", e)),
+        }
+    }
+}
+
+/// Where the snapshot of the database at `path` is kept between runs, named after its canonical path.
+fn snapshot_path(path: &str) -> Result<PathBuf, String> {
+    let canonical = if is_remote_path(path) {
+        path.to_string()
+    } else {
+        let canonical = std::fs::canonicalize(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        canonical.display().to_string()
+    };
+    let name: String = Sha256::digest(canonical.as_bytes())
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let dir = eframe::storage_dir("RustPass").ok_or_else(|| String::from("No local data folder to keep snapshots in"))?;
+    Ok(dir.join("snapshots").join(format!("{}.kdbx", name)))
+}
+
+/// A copy of `db` fit to be kept as a snapshot: every field and tag but the title is replaced by its SHA-256,
+/// and history and attachments are dropped, so changes still show up without storing the values.
+fn hashed_snapshot(db: &Database) -> Database {
+    let mut snapshot = db.clone();
+    if !matches!(snapshot.config.version, DatabaseVersion::KDB4(_)) {
+        snapshot.config = DatabaseConfig::default();
+    }
+    snapshot.header_attachments.clear();
+    hash_group_fields(&mut snapshot.root);
+    snapshot
+}
+
+fn hash_group_fields(group: &mut Group) {
+    let hash = |text: &mut Option<String>| {
+        if let Some(text) = text {
+            *text = Sha256::digest(text.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        }
+    };
+    for node in &mut group.children {
+        match node {
+            Node::Group(child) => {
+                hash(&mut child.notes);
+                hash(&mut child.default_autotype_sequence);
+                hash_group_fields(child);
+            }
+            Node::Entry(entry) => {
+                entry.history = None;
+                // Split and hashed one by one, so tags are still compared as a set
+                entry.tags = tag_set(entry)
+                    .iter()
+                    .map(|tag| Sha256::digest(tag.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect())
+                    .collect();
+                // Normalized first, so a placeholder written in another case still hashes the same
+                let hash_sequence = |sequence: &mut Option<String>| {
+                    if let Some(sequence) = sequence {
+                        *sequence = Sha256::digest(normalize_sequence(sequence).as_bytes())
+                            .iter()
+                            .map(|b| format!("{:02x}", b))
+                            .collect();
+                    }
+                };
+                if let Some(autotype) = &mut entry.autotype {
+                    hash_sequence(&mut autotype.sequence);
+                    for association in &mut autotype.associations {
+                        hash_sequence(&mut association.window);
+                        hash_sequence(&mut association.sequence);
+                    }
+                }
+                for (name, value) in &mut entry.fields {
+                    if name == "Title" {
+                        continue;
+                    }
+                    let digest = match value {
+                        Value::Bytes(bytes) => Sha256::digest(bytes),
+                        Value::Unprotected(text) => Sha256::digest(text.as_bytes()),
+                        Value::Protected(secret) => Sha256::digest(secret.unsecure()),
+                    };
+                    // Keep binaries binary so they are still compared as attachments
+                    *value = match value {
+                        Value::Bytes(_) => Value::Bytes(digest.to_vec()),
+                        _ => Value::Unprotected(digest.iter().map(|b| format!("{:02x}", b)).collect()),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Names of all fields used by any entry of `databases` except the password, sorted.
+fn field_names(databases: &[&Database]) -> Vec<String> {
+    fn collect<'a>(group: &'a Group, names: &mut BTreeSet<&'a str>) {
+        for entry in group.entries() {
+            names.extend(entry.fields.keys().map(String::as_str).filter(|name| *name != "Password"));
+        }
+        for child in group.groups() {
+            collect(child, names);
+        }
+    }
+    let mut names = BTreeSet::new();
+    for db in databases {
+        collect(&db.root, &mut names);
+    }
+    names.into_iter().map(String::from).collect()
+}
+
+pub(crate) fn count_entries(db: &Database) -> usize {
+    count_group_entries(&db.root)
+}
+
+pub(crate) fn count_group_entries(group: &Group) -> usize {
+    group.entries().len() + group.groups().iter().map(|g| count_group_entries(g)).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keepass::config::KdfConfig;
+
+    /// An empty database whose key derivation is kept cheap, so the tests stay fast.
+    fn empty_database() -> Database {
+        Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 16 },
+            ..DatabaseConfig::default()
+        })
+    }
+
+    fn save(db: &Database, password: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        db.save(&mut data, DatabaseKey::new().with_password(password)).expect("the database saves");
+        data
+    }
+
+    /// A saved database holding one entry.
+    fn saved_database(password: &str) -> Vec<u8> {
+        let mut db = empty_database();
+        let mut entry = keepass::db::Entry::new();
+        entry.fields.insert(String::from("Title"), Value::Unprotected(String::from("Mail")));
+        db.root.add_child(entry);
+        save(&db, password)
+    }
+
+    /// Opens `db` the way a real file is opened, after saving it.
+    fn reopened(db: &Database) -> Database {
+        match parse_database(&save(db, "pw"), DatabaseKey::new().with_password("pw")) {
+            Ok(db) => db,
+            Err(failure) => panic!("the database did not reopen: {}", failure.message),
+        }
+    }
+
+    fn compare(db1: &Database, db2: &Database) -> Vec<Difference> {
+        CompareOptions::default().compare_databases(db1, db2, &AtomicBool::new(false), &|_, _| {})
+    }
+
+    fn open_failure(data: &[u8], password: &str) -> OpenFailure {
+        match parse_database(data, DatabaseKey::new().with_password(password)) {
+            Ok(_) => panic!("the database opened"),
+            Err(failure) => failure,
+        }
+    }
+
+    #[test]
+    fn wrong_password_is_reported_as_such() {
+        let failure = open_failure(&saved_database("pw"), "wrong");
+        assert_eq!(failure.message, "Incorrect password or key file");
+        assert!(!failure.transient);
+    }
+
+    #[test]
+    fn damaged_file_is_reported_as_corrupt() {
+        let mut data = saved_database("pw");
+        let last = data.len() - 40;
+        data[last] ^= 0xff;
+        let OpenFailure { message, transient } = open_failure(&data, "pw");
+        assert!(message.starts_with("File appears corrupt or is not a KeePass database"), "{}", message);
+        // What a file caught halfway through being saved looks like, so it is retried
+        assert!(transient);
+    }
+
+    #[test]
+    fn truncated_file_is_retried() {
+        let data = saved_database("pw");
+        assert!(open_failure(&data[..data.len() / 2], "pw").transient);
+    }
+
+    #[test]
+    fn read_error_is_retried() {
+        let error = DatabaseOpenError::Io(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        assert!(is_partial_write(&error));
+    }
+
+    #[test]
+    fn unsupported_algorithm_is_not_retried() {
+        let error = DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::InvalidFixedCipherID { cid: 4 });
+        assert!(!is_partial_write(&error));
+        assert!(!is_partial_write(&DatabaseOpenError::UnsupportedVersion));
+    }
+
+    /// The first 12 bytes of a KeePass file: both signatures, then the format version.
+    fn header(signature2: [u8; 4], version: [u8; 4]) -> Vec<u8> {
+        [[0x03, 0xd9, 0xa2, 0x9a], signature2, version].concat()
+    }
+
+    fn refused(data: &[u8]) -> OpenFailure {
+        match check_signature(data) {
+            Ok(version) => panic!("accepted as {}", version),
+            Err(failure) => failure,
+        }
+    }
+
+    #[test]
+    fn formats_are_detected_from_the_signature() {
+        // KeePass 1.x keeps its cipher flags where later formats keep the version
+        let kdb = header([0x65, 0xfb, 0x4b, 0xb5], [0x03, 0x00, 0x00, 0x00]);
+        assert!(matches!(check_signature(&kdb), Ok(DatabaseVersion::KDB(_))));
+        let kdbx3 = header([0x67, 0xfb, 0x4b, 0xb5], [0x01, 0x00, 0x03, 0x00]);
+        assert!(matches!(check_signature(&kdbx3), Ok(DatabaseVersion::KDB3(1))));
+        let kdbx4 = header([0x67, 0xfb, 0x4b, 0xb5], [0x00, 0x00, 0x04, 0x00]);
+        assert!(matches!(check_signature(&kdbx4), Ok(DatabaseVersion::KDB4(0))));
+        assert!(matches!(check_signature(&saved_database("pw")), Ok(DatabaseVersion::KDB4(_))));
+    }
+
+    #[test]
+    fn pre_release_kdbx_is_refused() {
+        let failure = refused(&header([0x66, 0xfb, 0x4b, 0xb5], [0x00, 0x00, 0x01, 0x00]));
+        assert_eq!(
+            failure.message,
+            "This is a pre-release KeePass 2 database, which is not supported; open and save it in KeePass 2.x to upgrade it"
+        );
+        assert!(!failure.transient);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        let zip = b"PK\x03\x04\x14\x00\x00\x00\x08\x00\x00\x00";
+        for data in [&zip[..], &header([0x67, 0xfb, 0x4b, 0xb5], [0x00, 0x00, 0x05, 0x00]), &[0x03, 0xd9], &[]] {
+            assert_eq!(refused(data).message, "File is not a KeePass database (unrecognized file signature)");
+        }
+    }
+
+    #[test]
+    fn empty_databases_have_no_differences() {
+        let (db1, db2) = (reopened(&empty_database()), reopened(&empty_database()));
+        assert_eq!((count_entries(&db1), count_entries(&db2)), (0, 0));
+        assert!(compare(&db1, &db2).is_empty());
+    }
+
+    #[test]
+    fn nested_empty_groups_have_no_differences() {
+        let mut nested = empty_database();
+        let mut work = Group::new("Work");
+        let mut servers = Group::new("Servers");
+        servers.add_child(Group::new("Retired"));
+        work.add_child(servers);
+        nested.root.add_child(work);
+        nested.root.add_child(Group::new("Personal"));
+        let (db1, db2) = (reopened(&nested), reopened(&nested));
+        assert_eq!((count_entries(&db1), count_entries(&db2)), (0, 0));
+        assert!(compare(&db1, &db2).is_empty());
+        // Against a database without them, only the groups themselves are missing
+        let found = compare(&db1, &reopened(&empty_database()));
+        assert!(!found.is_empty() && found.iter().all(|diff| diff.diff_type.kind().is_group()));
+    }
+
+    #[test]
+    fn snapshots_keep_no_tags() {
+        let mut db = empty_database();
+        let mut entry = keepass::db::Entry::new();
+        entry.fields.insert(String::from("Title"), Value::Unprotected(String::from("Mail")));
+        db.root.add_child(entry);
+        let tagged = |tags: &str| {
+            let mut db = db.clone();
+            if let Some(Node::Entry(entry)) = db.root.children.first_mut() {
+                entry.tags = vec![tags.to_string()];
+            }
+            hashed_snapshot(&db)
+        };
+        let snapshot = tagged("work, private");
+        let Some(Node::Entry(entry)) = snapshot.root.children.first() else {
+            panic!("the snapshot lost its entry");
+        };
+        assert_eq!(entry.tags.len(), 2);
+        assert!(entry.tags.iter().all(|tag| !tag.contains("work") && !tag.contains("private")));
+        // Still a set, so reordering is no change and a new tag is
+        assert!(compare(&snapshot, &tagged("private;work")).is_empty());
+        assert_eq!(compare(&snapshot, &tagged("work, private, travel")).len(), 1);
+    }
+
+    fn download(body: &[u8], total: Option<u64>) -> Result<Vec<u8>, String> {
+        read_download(body, total, 8, &AtomicBool::new(false), &|_, _| {})
+    }
+
+    #[test]
+    fn downloads_up_to_the_limit_arrive_whole() {
+        assert_eq!(download(b"12345678", Some(8)), Ok(b"12345678".to_vec()));
+        assert_eq!(download(b"1234", None), Ok(b"1234".to_vec()));
+    }
+
+    #[test]
+    fn oversized_downloads_are_refused() {
+        let refused = |result: Result<Vec<u8>, String>| result.is_err_and(|e| e.starts_with("Download too large"));
+        // Announced, so nothing needs to be read
+        assert!(refused(download(b"", Some(9))));
+        // Unannounced or understated, so only noticed on arrival
+        assert!(refused(download(b"123456789", None)));
+        assert!(refused(download(b"123456789", Some(4))));
+    }
+
+    fn hex_bytes(hex: &str) -> Vec<u8> {
+        decode_hex(hex).expect("the test id is valid hex")
+    }
+
+    #[test]
+    fn aes_128_outer_cipher_is_named() {
+        let error = DatabaseIntegrityError::OuterCipher(OuterCipherConfigError::InvalidOuterCipherID {
+            cid: hex_bytes("61ab05a1946441c38d743a563df8dd35"),
+        });
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("AES-128"));
+    }
+
+    #[test]
+    fn unknown_outer_cipher_shows_its_id() {
+        let error = DatabaseIntegrityError::OuterCipher(OuterCipherConfigError::InvalidOuterCipherID {
+            cid: hex_bytes("00112233445566778899aabbccddeeff"),
+        });
+        assert_eq!(
+            unsupported_algorithm(&error).as_deref(),
+            Some("an unknown cipher (00112233445566778899aabbccddeeff)")
+        );
+    }
+
+    #[test]
+    fn arcfour_inner_cipher_is_named() {
+        let error = DatabaseIntegrityError::InnerCipher(InnerCipherConfigError::InvalidInnerCipherID { cid: 1 });
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("ArcFour to protect passwords"));
+        let error = DatabaseIntegrityError::InnerCipher(InnerCipherConfigError::InvalidInnerCipherID { cid: 9 });
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("an unknown password protection cipher (9)"));
+    }
+
+    #[test]
+    fn unknown_kdf_shows_its_uuid() {
+        let error = DatabaseIntegrityError::KdfSettings(KdfConfigError::InvalidKDFUUID {
+            uuid: hex_bytes("0123456789abcdef0123456789abcdef"),
+        });
+        assert_eq!(
+            unsupported_algorithm(&error).as_deref(),
+            Some("an unknown key derivation function (0123456789abcdef0123456789abcdef)")
+        );
+    }
+
+    #[test]
+    fn unsupported_argon2_version_is_named() {
+        let error = DatabaseIntegrityError::KdfSettings(KdfConfigError::InvalidKDFVersion { version: 0x10 });
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("Argon2 version 0x10"));
+    }
+
+    #[test]
+    fn unknown_compression_is_named() {
+        let error =
+            DatabaseIntegrityError::Compression(CompressionConfigError::InvalidCompressionSuite { cid: 7 });
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("an unknown compression algorithm (7)"));
+    }
+
+    #[test]
+    fn keepass_1_ciphers_are_named() {
+        let error = DatabaseIntegrityError::InvalidFixedCipherID { cid: 4 };
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("ArcFour"));
+        let error = DatabaseIntegrityError::InvalidFixedCipherID { cid: 0x10 };
+        assert_eq!(unsupported_algorithm(&error).as_deref(), Some("an unknown cipher (flags 0x10)"));
+    }
+
+    #[test]
+    fn other_integrity_errors_pass_through() {
+        let error = DatabaseIntegrityError::HeaderHashMismatch;
+        assert_eq!(unsupported_algorithm(&error), None);
+        let message = describe_open_error(&DatabaseOpenError::DatabaseIntegrity(error));
+        assert!(message.starts_with("File appears corrupt or is not a KeePass database"), "{}", message);
+    }
+
+    #[test]
+    fn unsupported_algorithm_is_not_blamed_on_the_password() {
+        let error = DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::InvalidFixedCipherID { cid: 4 });
+        assert_eq!(
+            describe_open_error(&error),
+            "Database uses ArcFour which isn't supported; this is a limitation of RustPass, not a wrong password"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Small databases built in memory for the tests, and temporary `.kdbx` files written from them.
+
+use keepass::config::{DatabaseConfig, KdfConfig};
+use keepass::db::{Entry, Group, Value};
+use keepass::{Database, DatabaseKey};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Builds an entry field by field; the password and protected fields are stored memory-protected.
+pub(crate) struct EntryBuilder {
+    entry: Entry,
+}
+
+impl EntryBuilder {
+    pub(crate) fn new(title: &str) -> Self {
+        EntryBuilder { entry: Entry::new() }.field("Title", title)
+    }
+
+    pub(crate) fn username(self, username: &str) -> Self {
+        self.field("UserName", username)
+    }
+
+    pub(crate) fn password(self, password: &str) -> Self {
+        self.protected_field("Password", password)
+    }
+
+    pub(crate) fn url(self, url: &str) -> Self {
+        self.field("URL", url)
+    }
+
+    pub(crate) fn field(mut self, name: &str, value: &str) -> Self {
+        self.entry.fields.insert(name.to_string(), Value::Unprotected(value.to_string()));
+        self
+    }
+
+    pub(crate) fn protected_field(mut self, name: &str, value: &str) -> Self {
+        self.entry.fields.insert(name.to_string(), Value::Protected(value.as_bytes().into()));
+        self
+    }
+
+    pub(crate) fn build(self) -> Entry {
+        self.entry
+    }
+}
+
+/// Builds a group with its entries and subgroups.
+pub(crate) struct GroupBuilder {
+    group: Group,
+}
+
+impl GroupBuilder {
+    pub(crate) fn new(name: &str) -> Self {
+        GroupBuilder { group: Group::new(name) }
+    }
+
+    pub(crate) fn entry(mut self, entry: EntryBuilder) -> Self {
+        self.group.add_child(entry.build());
+        self
+    }
+
+    pub(crate) fn group(mut self, group: GroupBuilder) -> Self {
+        self.group.add_child(group.build());
+        self
+    }
+
+    pub(crate) fn build(self) -> Group {
+        self.group
+    }
+}
+
+/// Builds a database whose root holds the given entries and groups. Its key derivation is kept
+/// cheap, so the tests that write and reopen files stay fast.
+pub(crate) struct DatabaseBuilder {
+    db: Database,
+}
+
+impl DatabaseBuilder {
+    pub(crate) fn new() -> Self {
+        let config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 16 },
+            ..DatabaseConfig::default()
+        };
+        DatabaseBuilder { db: Database::new(config) }
+    }
+
+    pub(crate) fn entry(mut self, entry: EntryBuilder) -> Self {
+        self.db.root.add_child(entry.build());
+        self
+    }
+
+    pub(crate) fn group(mut self, group: GroupBuilder) -> Self {
+        self.db.root.add_child(group.build());
+        self
+    }
+
+    pub(crate) fn build(self) -> Database {
+        self.db
+    }
+}
+
+/// The first entry titled `title` anywhere in `db`, to change one side of a cloned database.
+pub(crate) fn entry_mut<'a>(db: &'a mut Database, title: &str) -> &'a mut Entry {
+    fn find<'a>(group: &'a mut Group, title: &str) -> Option<&'a mut Entry> {
+        if group.entries().iter().any(|entry| entry.get_title() == Some(title)) {
+            return group.entries_mut().into_iter().find(|entry| entry.get_title() == Some(title));
+        }
+        group.groups_mut().into_iter().find_map(|child| find(child, title))
+    }
+    find(&mut db.root, title).unwrap_or_else(|| panic!("no entry titled {:?}", title))
+}
+
+/// A database written to the temporary folder, deleted again when dropped.
+pub(crate) struct TempDatabase {
+    path: PathBuf,
+}
+
+impl TempDatabase {
+    pub(crate) fn write(db: &Database, password: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("rustpass-test-{}.kdbx", Uuid::new_v4()));
+        let mut file = std::fs::File::create(&path).expect("the temporary folder is writable");
+        db.save(&mut file, DatabaseKey::new().with_password(password))
+            .expect("the database saves");
+        TempDatabase { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn open(&self, password: &str) -> Database {
+        let mut file = std::fs::File::open(&self.path).expect("the temporary database exists");
+        Database::open(&mut file, DatabaseKey::new().with_password(password)).expect("the database opens")
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}